@@ -0,0 +1,214 @@
+use crate::sysctrl::SysCtrl;
+use crate::upgrade::load_aps;
+use anyhow_ext::{anyhow, Context, Result};
+use chrono::Local;
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+use tokio::time::sleep;
+
+/// 默认取的日志
+pub const DEFAULT_LOG: &str = "messages";
+
+/// 自动取日志时的保存目录
+pub const LOG_DIR: &str = "aplog";
+
+/// 自动取日志时上传后等待的秒数
+pub const UPLOAD_WAIT: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogAction {
+    List,  // 列出基站上的日志
+    Fetch, // 上传并下载
+}
+
+impl FromStr for LogAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(Self::List),
+            "fetch" => Ok(Self::Fetch),
+            _ => Err(format!("unknown action {}, use list/fetch", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct LogOpt {
+    /// list/fetch
+    pub action: LogAction,
+
+    /// 集群地址
+    #[structopt(long, default_value = "https://10.11.173.231:9900")]
+    pub cluster: String,
+
+    /// 基站 mac，可以填多个
+    #[structopt(long)]
+    pub mac: Vec<String>,
+
+    /// 基站 mac 列表文件，和 --mac 一起用
+    #[structopt(long)]
+    pub aps: Option<String>,
+
+    /// blob 的 org/store，如 siyingyu/0006
+    #[structopt(long, default_value = "")]
+    pub store: String,
+
+    /// 要取的日志名，不填取 messages
+    #[structopt(long)]
+    pub file: Vec<String>,
+
+    /// 上传后等这么多秒再下载
+    #[structopt(long, default_value = "3")]
+    pub wait: u64,
+
+    /// 保存目录
+    #[structopt(long, default_value = "aplog")]
+    pub out: String,
+
+    /// 保留 gz，不解压
+    #[structopt(long)]
+    pub keep_gz: bool,
+}
+
+/**
+ * blob 路径里的 mac，大写去掉冒号
+ */
+pub fn blob_mac(mac: &str) -> String {
+    mac.replace([':', '-'], "").to_uppercase()
+}
+
+// 保留原始数据时文件名带上 .gz
+fn gz_name(file: &str) -> String {
+    if file.ends_with(".gz") {
+        file.to_string()
+    } else {
+        format!("{}.gz", file)
+    }
+}
+
+/**
+ * gzip 的数据解压，不是的原样返回
+ */
+pub fn gunzip(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return Ok(data);
+    }
+    let mut out = Vec::new();
+    MultiGzDecoder::new(&data[..]).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/**
+ * log/upload 不带文件时返回日志列表
+ */
+pub async fn list(rpc: &SysCtrl) -> Result<Vec<String>> {
+    let data = rpc.log_upload(&[], "forever").await?;
+    let items = [&data["file"], &data["list"], &data]
+        .into_iter()
+        .find_map(|d| d.as_array())
+        .cloned()
+        .unwrap_or_default();
+    Ok(items
+        .iter()
+        .filter_map(|v| match v {
+            Value::String(s) => Some(s.clone()),
+            v => v["name"].as_str().map(|s| s.to_string()),
+        })
+        .collect())
+}
+
+/**
+ * 上传日志到 blob 再下载，按 <MAC>_<时间>_<文件> 保存，返回保存的文件
+ */
+pub async fn collect(
+    rpc: &SysCtrl,
+    store: &str,
+    files: &[String],
+    out: &str,
+    wait: u64,
+    keep_gz: bool,
+) -> Result<Vec<PathBuf>> {
+    if store.is_empty() {
+        return Err(anyhow!("blob store not set, use --store org/store"));
+    }
+    let files = if files.is_empty() {
+        vec![DEFAULT_LOG.to_string()]
+    } else {
+        files.to_vec()
+    };
+    rpc.log_upload(&files, "forever").await?;
+    sleep(Duration::from_secs(wait)).await;
+
+    fs::create_dir_all(out).with_context(|| format!("create {} failed", out))?;
+    let mac = blob_mac(rpc.name());
+    let now = Local::now().format("%Y%m%d_%H%M%S");
+    let mut saved = Vec::new();
+    for file in &files {
+        let data = match rpc.blob(&format!("{}/aplog/{}/{}", store, mac, file)).await {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("download {} {} failed, {}", mac, file, e);
+                continue;
+            }
+        };
+        let (data, name) = if keep_gz {
+            (data, file.clone())
+        } else {
+            // 解压失败的保留原始数据，不影响其他文件
+            match gunzip(data.clone()) {
+                Ok(d) => (d, file.trim_end_matches(".gz").to_string()),
+                Err(e) => {
+                    warn!("gunzip {} {} failed, keep gz, {}", mac, file, e);
+                    (data, gz_name(file))
+                }
+            }
+        };
+        let fp = Path::new(out).join(format!("{}_{}_{}", mac, now, name));
+        fs::write(&fp, data).with_context(|| format!("write {:?} failed", fp))?;
+        saved.push(fp);
+    }
+    info!("{} collect {} log to {}", mac, saved.len(), out);
+    Ok(saved)
+}
+
+/**
+ * log 子命令，替代 getlog.bat 和 log_get.sh
+ */
+pub async fn run(opt: &LogOpt) -> Result<()> {
+    let mut macs = opt.mac.clone();
+    if let Some(fp) = &opt.aps {
+        macs.extend(load_aps(fp)?.iter().map(|m| m.to_lowercase()));
+    }
+    if macs.is_empty() {
+        return Err(anyhow!("no ap, use --mac or --aps"));
+    }
+    for mac in &macs {
+        let rpc = SysCtrl::cluster(&opt.cluster, mac)?;
+        match opt.action {
+            LogAction::List => match list(&rpc).await {
+                Ok(files) => println!("{} {}", mac, files.join(" ")),
+                Err(e) => warn!("{} list log failed, {}", mac, e),
+            },
+            LogAction::Fetch => {
+                let ret = collect(&rpc, &opt.store, &opt.file, &opt.out, opt.wait, opt.keep_gz);
+                match ret.await {
+                    Ok(saved) => {
+                        for fp in saved {
+                            println!("{} {}", mac, fp.display());
+                        }
+                    }
+                    Err(e) => warn!("{} fetch log failed, {}", mac, e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
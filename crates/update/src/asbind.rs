@@ -1,83 +1,337 @@
-const EW: &str = "127.0.0.1:9000";
-const UC: &str = "god.2";
-const BAK_URL: &str = "http://172.16.120.59:8083/shopweb-webapp/ogi/ew/httpHandler?customerCode=god";
-const ALLSTAR_LOGIN: &str = "http://172.16.120.59:8084/proxy/allstar/user/login";
-const PS_BIND: &str = "http://172.16.120.59:8084/proxy/prismart/esl/god/2/binding";
-const AURORA_BIND: &str = "http://172.16.120.59:8084/proxy/aurora/deviceGoods/bind";
-const AURORA_CHECK: &str = "http://172.16.120.59:8084/proxy/aurora/deviceGoods/getList";
-const AURORA_UN: &str = "superuser";
-const AURORA_PW: &str = "ACD7600A8A2F0AA9AAB51C29F50C64A1";
+use crate::bind::{item_esl, item_sku, BindAction, Binder, Binding, Platform};
+use crate::reset::get_esl;
+use crate::session::{Session, SharedSession};
+use anyhow_ext::Result;
+use chrono::Local;
+use forever::eslog::{EslAction, EslLog};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::time::sleep;
+
 const AURORA_DEVICE: [&str; 2] = ["218972322667593985", "223973162683266819"];
 
-struct ASUpdate {
+/// 查询设备显示内容的间隔，单位为秒
+const VERIFY_POLL: u64 = 5;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct LcdOpt {
+    /// 平台地址和账号的配置文件
+    #[structopt(long, default_value = "platform.json")]
+    pub auth: String,
+
+    /// 价签列表，一行一个
+    #[structopt(long, default_value = "esl.txt")]
+    pub epd: String,
+
+    /// aurora lcd 设备 id，不填用默认的两台
+    #[structopt(long)]
+    pub device: Vec<String>,
+
+    /// 绑定的商品
+    #[structopt(long, default_value = "123")]
+    pub sku: String,
+
+    /// prismart 的 customer/store
+    #[structopt(long, default_value = "god/2")]
+    pub store: String,
+
+    /// 修改商品价格的接口，价签和 lcd 都按商品刷新
+    #[structopt(long, default_value = "proxy/allstar/goods/save")]
+    pub goods_url: String,
+
+    /// 不更新价签
+    #[structopt(long)]
+    pub no_epd: bool,
+
+    /// 不更新 lcd
+    #[structopt(long)]
+    pub no_lcd: bool,
+
+    /// lcd 每轮切换促销图标
+    #[structopt(long)]
+    pub icon: bool,
+
+    /// 每轮间隔，单位为秒
+    #[structopt(long, default_value = "60")]
+    pub interval: u64,
+
+    /// 轮数，0 为一直跑
+    #[structopt(long, default_value = "0")]
+    pub cycles: u32,
+
+    /// ew 日志，填了价签还要在日志里刷新成功才算通过
+    #[structopt(long)]
+    pub ewlog: Option<String>,
+
+    /// 等设备显示新价格的超时，单位为秒
+    #[structopt(long, default_value = "120")]
+    pub verify_timeout: u64,
+}
+
+/**
+ * 每种屏的累计结果
+ */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CycleStat {
+    pub ok: u32,
+    pub failed: u32,
+}
+
+/**
+ * 价签和 aurora lcd 一起循环更新：改价、绑定、查询校验
+ */
+pub struct ASUpdate {
     update_price: i32,
     epd_list: Vec<String>,
+    lcd_list: Vec<String>,
     epd_status: bool,
     lcd_status: bool,
     lcd_update_icon: bool,
-    s: Client,
-    aurora_login_info: serde_json::Value,
-    start_update_time: Option<u64>,
+    session: SharedSession,
+    start_update_time: Option<i64>,
+    ewlog_seek: u64, // 本轮开始时 ew 日志的位置
+    opt: LcdOpt,
+    pub epd_stat: CycleStat,
+    pub lcd_stat: CycleStat,
 }
 
+impl ASUpdate {
+    pub fn new(session: SharedSession, opt: LcdOpt) -> Self {
+        let lcd_list = if opt.device.is_empty() {
+            AURORA_DEVICE.iter().map(|d| d.to_string()).collect()
+        } else {
+            opt.device.clone()
+        };
+        Self {
+            update_price: 0,
+            epd_list: if opt.no_epd {
+                Vec::new()
+            } else {
+                get_esl(&opt.epd)
+            },
+            lcd_list,
+            epd_status: !opt.no_epd,
+            lcd_status: !opt.no_lcd,
+            lcd_update_icon: opt.icon,
+            session,
+            start_update_time: None,
+            ewlog_seek: 0,
+            opt,
+            epd_stat: CycleStat::default(),
+            lcd_stat: CycleStat::default(),
+        }
+    }
 
-fn get_esl(fp: &str) -> Vec<String> {
-    let file = File::open(fp).expect("Failed to open file");
-    let reader = BufReader::new(file);
-    let mut esl_list = Vec::new();
-    
-    for line in reader.lines() {
-        if let Ok(l) = line {
-            if let Some(id) = l.strip_prefix("eslid=") {
-                esl_list.push(id.trim().to_owned());
-            }
+    // 改商品价格，绑定了这个商品的屏都会刷新
+    async fn push_goods(&self, cycle: u32) -> Result<()> {
+        let mut goods = json!({
+            "sku": self.opt.sku,
+            "name": format!("forever-{}", self.opt.sku),
+            "price": self.update_price,
+        });
+        if self.lcd_update_icon {
+            goods["extra"] = json!({ "promotion": cycle.is_multiple_of(2) });
         }
+        self.session.post(&self.opt.goods_url, &goods).await?;
+        Ok(())
     }
-    
-    esl_list
-}
 
-impl ASUpdate {
-    async fn run(&mut self) -> Result<(), reqwest::Error> {
-        let data: Vec<serde_json::Value> = self.epd_list.iter().map(|e| {
-            json!({
-                "eslId": e,
-                "goodsSku": "123",
-                "position": 0,
-                "extra": {}
+    // 绑定后查询，商品一致并且显示了本轮价格算成功
+    async fn bind_verify(&self, platform: Platform, list: &[String]) -> Result<CycleStat> {
+        let binder = Binder::new(platform, &self.opt.store, self.session.clone());
+        let binding: Vec<Binding> = list
+            .iter()
+            .map(|e| Binding {
+                esl: e.clone(),
+                sku: self.opt.sku.clone(),
+                position: 0,
             })
-        }).collect();
-        
-        let response = self.s.post(PS_BIND)
-            .json(&data)
-            .header(reqwest::header::USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36")
-            .header(reqwest::header::AUTHORIZATION, format!("Bearer {}", self.aurora_login_info["data"]["access_token"].as_str().unwrap()))
-            .send()
-            .await?;
-        
-        println!("{:?}", response.text().await?);
+            .collect();
+        let mut bad: BTreeSet<String> = binder
+            .apply(BindAction::Bind, &binding)
+            .await
+            .into_iter()
+            .collect();
+        for m in binder.check(&binding, false).await? {
+            warn!(
+                "{:?} {} bind {:?}, expect {}",
+                platform, m.esl, m.actual, self.opt.sku
+            );
+            bad.insert(m.esl);
+        }
+        let pending = list.iter().filter(|e| !bad.contains(*e)).cloned().collect();
+        for e in self.wait_shown(&binder, platform, pending).await {
+            warn!(
+                "{:?} {} not show price {} in {}s",
+                platform, e, self.update_price, self.opt.verify_timeout
+            );
+            bad.insert(e);
+        }
+        let bad = bad.len() as u32;
+        Ok(CycleStat {
+            ok: (list.len() as u32).saturating_sub(bad),
+            failed: bad,
+        })
+    }
+
+    // 等设备显示本轮价格，返回超时还没显示的
+    // 查询结果没有价格时只能靠 ew 日志确认，所以价签要填 --ewlog
+    async fn wait_shown(
+        &self,
+        binder: &Binder,
+        platform: Platform,
+        mut pending: Vec<String>,
+    ) -> Vec<String> {
+        let ewlog = self
+            .opt
+            .ewlog
+            .as_ref()
+            .filter(|_| platform == Platform::Prismart);
+        let esl_log = EslLog::new();
+        let mut seek = self.ewlog_seek;
+        let mut refreshed = BTreeSet::new();
+        let start = Instant::now();
+        while !pending.is_empty() {
+            if let Some(fp) = ewlog {
+                if let Err(e) = read_refreshed(fp, &mut seek, &esl_log, &mut refreshed) {
+                    warn!("read {} failed, {}", fp, e);
+                }
+            }
+            let items = binder.items(&pending).await.unwrap_or_else(|e| {
+                warn!("{:?} query failed, {}", platform, e);
+                Vec::new()
+            });
+            let shown: BTreeSet<&str> = items
+                .iter()
+                .filter(|i| item_sku(i) == Some(self.opt.sku.as_str()))
+                .filter_map(|i| {
+                    let esl = item_esl(i)?;
+                    let price = item_price(i).map(|p| (p - self.update_price as f64).abs() < 0.005);
+                    let done = match ewlog {
+                        Some(_) => price != Some(false) && refreshed.contains(esl),
+                        None => price == Some(true),
+                    };
+                    done.then_some(esl)
+                })
+                .collect();
+            pending.retain(|e| !shown.contains(e.as_str()));
+            if pending.is_empty() || start.elapsed().as_secs() >= self.opt.verify_timeout {
+                break;
+            }
+            sleep(Duration::from_secs(VERIFY_POLL)).await;
+        }
+        pending
+    }
+
+    fn add(stat: &mut CycleStat, ret: Result<CycleStat>, count: usize) {
+        match ret {
+            Ok(s) => {
+                stat.ok += s.ok;
+                stat.failed += s.failed;
+            }
+            Err(e) => {
+                warn!("bind verify failed, {}", e);
+                stat.failed += count as u32;
+            }
+        }
+    }
+
+    /// 跑一轮
+    pub async fn run(&mut self, cycle: u32) -> Result<()> {
+        self.update_price += 1;
+        self.start_update_time = Some(Local::now().timestamp());
+        // 只看改价之后的刷新
+        if let Some(fp) = &self.opt.ewlog {
+            self.ewlog_seek = fs::metadata(fp).map_or(0, |m| m.len());
+        }
+        info!(
+            "cycle {} start, price={}, epd={}, lcd={}",
+            cycle,
+            self.update_price,
+            self.epd_list.len(),
+            self.lcd_list.len()
+        );
+        self.push_goods(cycle).await?;
+
+        if self.epd_status && !self.epd_list.is_empty() {
+            let ret = self.bind_verify(Platform::Prismart, &self.epd_list).await;
+            Self::add(&mut self.epd_stat, ret, self.epd_list.len());
+        }
+        if self.lcd_status && !self.lcd_list.is_empty() {
+            let ret = self.bind_verify(Platform::Aurora, &self.lcd_list).await;
+            Self::add(&mut self.lcd_stat, ret, self.lcd_list.len());
+        }
+        info!(
+            "cycle {} finish, use {}s; epd ok={} failed={}; lcd ok={} failed={}",
+            cycle,
+            Local::now().timestamp() - self.start_update_time.unwrap_or_default(),
+            self.epd_stat.ok,
+            self.epd_stat.failed,
+            self.lcd_stat.ok,
+            self.lcd_stat.failed
+        );
         Ok(())
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
-    let mut as_update = ASUpdate {
-        update_price: 0,
-        epd_list: get_esl("esl.txt"),
-        epd_status: true,
-        lcd_status: false,
-        lcd_update_icon: true,
-        s: reqwest::Client::new(),
-        aurora_login_info: reqwest::Client::new().post(ALLSTAR_LOGIN)
-            .json(&json!({"username": AURORA_UN, "password": AURORA_PW}))
-            .send()
-            .await?
-            .json()
-            .await?,
-        start_update_time: None,
-    };
-    
-    as_update.run().await?;
+// 条目里的价格，兼容 price/goodsPrice/salePrice 和嵌套的 goods
+fn item_price(item: &Value) -> Option<f64> {
+    [item, &item["goods"], &item["goodsInfo"]]
+        .into_iter()
+        .find_map(|v| {
+            ["price", "goodsPrice", "salePrice"]
+                .iter()
+                .find_map(|k| match &v[*k] {
+                    Value::Number(n) => n.as_f64(),
+                    Value::String(s) => s.parse().ok(),
+                    _ => None,
+                })
+        })
+}
+
+// 从上次位置读新写入的 ew 日志，记下刷新成功的价签
+fn read_refreshed(
+    fp: &str,
+    seek: &mut u64,
+    esl_log: &EslLog,
+    refreshed: &mut BTreeSet<String>,
+) -> Result<()> {
+    esl_log.tail(fp, seek, |ev| {
+        if matches!(ev.action, EslAction::Finish(_)) && !ev.action.failed() {
+            refreshed.insert(ev.eslid);
+        }
+    })?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/**
+ * lcd 子命令，按间隔循环
+ */
+pub async fn run(opt: &LcdOpt) -> Result<()> {
+    let session = Session::load(&opt.auth)?;
+    let mut updater = ASUpdate::new(session, opt.clone());
+    let mut cycle = 0;
+    loop {
+        cycle += 1;
+        if let Err(e) = updater.run(cycle).await {
+            warn!("cycle {} failed, {}", cycle, e);
+        }
+        if opt.cycles > 0 && cycle >= opt.cycles {
+            break;
+        }
+        sleep(Duration::from_secs(opt.interval)).await;
+    }
+    println!(
+        "{} cycles; epd ok={} failed={}; lcd ok={} failed={}",
+        cycle,
+        updater.epd_stat.ok,
+        updater.epd_stat.failed,
+        updater.lcd_stat.ok,
+        updater.lcd_stat.failed
+    );
+    Ok(())
+}
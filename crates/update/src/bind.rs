@@ -0,0 +1,363 @@
+use crate::session::{Session, SharedSession};
+use anyhow_ext::{anyhow, Context, Result};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// 默认每批绑定的价签数
+pub const BATCH: usize = 100;
+
+/**
+ * 绑定平台
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platform {
+    Prismart,
+    Aurora,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "prismart" => Ok(Self::Prismart),
+            "aurora" => Ok(Self::Aurora),
+            _ => Err(format!("unknown platform {}, use prismart/aurora", s)),
+        }
+    }
+}
+
+/**
+ * 绑定动作
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindAction {
+    Bind,
+    Unbind,
+    Check, // 只查询，和映射文件比较
+}
+
+impl FromStr for BindAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bind" => Ok(Self::Bind),
+            "unbind" => Ok(Self::Unbind),
+            "check" => Ok(Self::Check),
+            _ => Err(format!("unknown action {}, use bind/unbind/check", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct BindOpt {
+    /// bind/unbind/check
+    pub action: BindAction,
+
+    /// 平台 prismart/aurora
+    #[structopt(long, default_value = "prismart")]
+    pub platform: Platform,
+
+    /// 映射文件，一行 esl,sku[,position]
+    #[structopt(long, default_value = "bind.csv")]
+    pub map: String,
+
+    /// 平台地址和账号的配置文件
+    #[structopt(long, default_value = "platform.json")]
+    pub auth: String,
+
+    /// prismart 的 customer/store
+    #[structopt(long, default_value = "god/2")]
+    pub store: String,
+
+    /// 每批绑定的价签数
+    #[structopt(long, default_value = "100")]
+    pub batch: usize,
+
+    /// bind/unbind 之后是否再查询一遍
+    #[structopt(long)]
+    pub verify: bool,
+
+    /// 不一致的价签写入这个文件
+    #[structopt(long, default_value = "bind_mismatch.txt")]
+    pub out: String,
+}
+
+/**
+ * 映射文件里的一条
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub esl: String,
+    pub sku: String,
+    pub position: u32,
+}
+
+/**
+ * 校验不一致的价签
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub esl: String,
+    pub expect: Option<String>, // 解绑时为 None
+    pub actual: Option<String>,
+}
+
+/**
+ ** 读取映射文件，跳过空行和 # 开头的行
+ */
+pub fn load_map(fp: &str) -> Result<Vec<Binding>> {
+    let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+    let mut list = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cols: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        if cols.len() < 2 || cols[0].is_empty() || cols[1].is_empty() {
+            return Err(anyhow!("{} line {}: need esl,sku", fp, i + 1));
+        }
+        let position = match cols.get(2) {
+            Some(p) => p
+                .parse()
+                .with_context(|| format!("{} line {}: bad position", fp, i + 1))?,
+            None => 0,
+        };
+        list.push(Binding {
+            esl: cols[0]
+                .strip_prefix("eslid=")
+                .unwrap_or(cols[0])
+                .to_string(),
+            sku: cols[1].to_string(),
+            position,
+        });
+    }
+    Ok(list)
+}
+
+pub struct Binder {
+    platform: Platform,
+    store: String, // prismart 的 customer/store，aurora 不用
+    batch: usize,
+    session: SharedSession,
+}
+
+impl Binder {
+    pub fn new(platform: Platform, store: &str, session: SharedSession) -> Self {
+        Self {
+            platform,
+            store: store.to_string(),
+            batch: BATCH,
+            session,
+        }
+    }
+
+    /// 每批的价签数，默认为 BATCH
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        match self.platform {
+            Platform::Prismart => format!("proxy/prismart/esl/{}/{}", self.store, path),
+            Platform::Aurora => format!("proxy/aurora/deviceGoods/{}", path),
+        }
+    }
+
+    // 绑定/解绑的请求体，两个平台字段不一样
+    fn body(&self, action: BindAction, list: &[Binding]) -> Value {
+        let esl: Vec<&str> = list.iter().map(|b| b.esl.as_str()).collect();
+        match (self.platform, action) {
+            (Platform::Prismart, BindAction::Bind) => list
+                .iter()
+                .map(|b| {
+                    json!({
+                        "eslId": b.esl,
+                        "goodsSku": b.sku,
+                        "position": b.position,
+                        "extra": {}
+                    })
+                })
+                .collect(),
+            (Platform::Prismart, _) => json!(esl),
+            (Platform::Aurora, BindAction::Bind) => {
+                let items: Vec<Value> = list
+                    .iter()
+                    .map(
+                        |b| json!({"deviceCode": b.esl, "goodsSku": b.sku, "position": b.position}),
+                    )
+                    .collect();
+                json!({ "list": items })
+            }
+            (Platform::Aurora, _) => json!({ "deviceCodes": esl }),
+        }
+    }
+
+    /**
+     * 分批绑定或解绑，返回请求失败的价签
+     */
+    pub async fn apply(&self, action: BindAction, list: &[Binding]) -> Vec<String> {
+        let path = match action {
+            BindAction::Unbind => "unbind",
+            _ => "bind",
+        };
+        let url = match self.platform {
+            Platform::Prismart if action == BindAction::Bind => self.url("binding"),
+            Platform::Prismart => self.url("unbinding"),
+            Platform::Aurora => self.url(path),
+        };
+        let mut failed = Vec::new();
+        for chunk in list.chunks(self.batch) {
+            match self.session.post(&url, &self.body(action, chunk)).await {
+                Ok(v) => info!("{} {} esl, {}", path, chunk.len(), v),
+                Err(e) => {
+                    warn!("{} {} esl failed, {}", path, chunk.len(), e);
+                    failed.extend(chunk.iter().map(|b| b.esl.clone()));
+                }
+            }
+        }
+        failed
+    }
+
+    /**
+     * 查询价签当前绑定的商品，esl -> sku
+     */
+    pub async fn query(&self, esl: &[String]) -> Result<BTreeMap<String, String>> {
+        let mut bound = BTreeMap::new();
+        for item in self.items(esl).await? {
+            if let (Some(esl), Some(sku)) = (item_esl(&item), item_sku(&item)) {
+                bound.insert(esl.to_string(), sku.to_string());
+            }
+        }
+        Ok(bound)
+    }
+
+    /**
+     * 查询绑定列表的原始条目，除了商品还带价格等信息
+     */
+    pub async fn items(&self, esl: &[String]) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        for chunk in esl.chunks(self.batch) {
+            let (url, body) = match self.platform {
+                Platform::Prismart => (self.url("binding/list"), json!({"eslIds": chunk})),
+                Platform::Aurora => (
+                    self.url("getList"),
+                    json!({"deviceCodes": chunk, "pageNum": 1, "pageSize": chunk.len()}),
+                ),
+            };
+            let v = self.session.post(&url, &body).await?;
+            items.extend(bound_items(&v));
+        }
+        Ok(items)
+    }
+
+    /**
+     * 和期望的绑定比较，解绑时期望为空
+     */
+    pub async fn check(&self, list: &[Binding], unbound: bool) -> Result<Vec<Mismatch>> {
+        let esl: Vec<String> = list.iter().map(|b| b.esl.clone()).collect();
+        let bound = self.query(&esl).await?;
+        Ok(list
+            .iter()
+            .filter_map(|b| {
+                let expect = (!unbound).then(|| b.sku.clone());
+                let actual = bound.get(&b.esl).cloned();
+                (expect != actual).then(|| Mismatch {
+                    esl: b.esl.clone(),
+                    expect,
+                    actual,
+                })
+            })
+            .collect())
+    }
+}
+
+// 返回里找绑定列表，兼容 data.list / data.records / data 几种格式
+fn bound_items(v: &Value) -> Vec<Value> {
+    let data = &v["data"];
+    [&data["list"], &data["records"], data]
+        .into_iter()
+        .find_map(|d| d.as_array())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// 条目里的价签或设备 id
+pub fn item_esl(item: &Value) -> Option<&str> {
+    ["eslId", "deviceCode", "deviceId"]
+        .iter()
+        .find_map(|k| item[*k].as_str())
+}
+
+/// 条目里绑定的商品
+pub fn item_sku(item: &Value) -> Option<&str> {
+    ["goodsSku", "sku", "skuCode"]
+        .iter()
+        .find_map(|k| item[*k].as_str())
+}
+
+/**
+ * bind 子命令
+ */
+pub async fn run(opt: &BindOpt) -> Result<()> {
+    let list = load_map(&opt.map)?;
+    info!("{:?} {} esl on {:?}", opt.action, list.len(), opt.platform);
+    let binder = Binder::new(opt.platform, &opt.store, Session::load(&opt.auth)?).batch(opt.batch);
+
+    if opt.action != BindAction::Check {
+        let failed = binder.apply(opt.action, &list).await;
+        println!("{:?} request failed: {}", opt.action, failed.len());
+        if !opt.verify {
+            return Ok(());
+        }
+    }
+    let mismatch = binder
+        .check(&list, opt.action == BindAction::Unbind)
+        .await?;
+    let mut text = String::new();
+    for m in &mismatch {
+        let line = format!(
+            "{:<14} expect={:<16} actual={}",
+            m.esl,
+            m.expect.as_deref().unwrap_or("-"),
+            m.actual.as_deref().unwrap_or("-")
+        );
+        println!("{}", line);
+        text.push_str(&line);
+        text.push('\n');
+    }
+    fs::write(&opt.out, text).with_context(|| format!("write {} failed", opt.out))?;
+    println!(
+        "{} of {} esl mismatch, write to {}",
+        mismatch.len(),
+        list.len(),
+        opt.out
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bound_items, item_esl, item_sku};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_bound() {
+        let mut items = bound_items(
+            &json!({"data": {"list": [{"deviceCode": "A", "goodsSku": "1"}, {"deviceCode": "B"}]}}),
+        );
+        items.extend(bound_items(&json!({"data": [{"eslId": "C", "sku": "3"}]})));
+        let bound: Vec<(&str, Option<&str>)> = items
+            .iter()
+            .filter_map(|i| Some((item_esl(i)?, item_sku(i))))
+            .collect();
+        assert_eq!(bound, vec![("A", Some("1")), ("B", None), ("C", Some("3"))]);
+    }
+}
@@ -0,0 +1,323 @@
+use crate::sysctrl::{ContainerInfo, ContainerSpec, SysCtrl};
+use crate::upgrade::load_aps;
+use anyhow_ext::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+
+/// 等待容器状态的查询间隔，单位为秒
+const WAIT_POLL: u64 = 5;
+
+/**
+ * 容器操作
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContainerAction {
+    List,    // 列出状态
+    Deploy,  // 没有的 run，已有的 update
+    Update,  // 按 spec 更新
+    Restart, // 重启 spec 里的容器
+    Drift,   // 只和 spec 比较
+}
+
+impl FromStr for ContainerAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(Self::List),
+            "deploy" => Ok(Self::Deploy),
+            "update" => Ok(Self::Update),
+            "restart" => Ok(Self::Restart),
+            "drift" => Ok(Self::Drift),
+            _ => Err(format!(
+                "unknown action {}, use list/deploy/update/restart/drift",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ContainerOpt {
+    /// list/deploy/update/restart/drift
+    pub action: ContainerAction,
+
+    /// 集群地址
+    #[structopt(long, default_value = "https://10.11.173.231:9900")]
+    pub cluster: String,
+
+    /// 基站 mac 列表文件，一行一个
+    #[structopt(long, default_value = "ap.txt")]
+    pub aps: String,
+
+    /// 容器声明文件，yaml 或 json
+    #[structopt(long, default_value = "containers.yaml")]
+    pub spec: String,
+
+    /// 只处理这些容器，不填为 spec 里全部
+    #[structopt(long)]
+    pub name: Vec<String>,
+
+    /// 同时处理的基站数
+    #[structopt(long, default_value = "5")]
+    pub concurrency: usize,
+
+    /// 等容器运行的超时，单位为秒
+    #[structopt(long, default_value = "180")]
+    pub timeout: u64,
+
+    /// 结果写入这个文件
+    #[structopt(long, default_value = "container_report.txt")]
+    pub out: String,
+}
+
+/**
+ * 声明文件，每个容器是 container/run 的参数，running 为期望状态
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContainerDecl {
+    #[serde(flatten)]
+    pub spec: ContainerSpec,
+    #[serde(default = "default_running")]
+    pub running: bool,
+}
+
+fn default_running() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpecFile {
+    pub containers: Vec<ContainerDecl>,
+}
+
+impl SpecFile {
+    pub fn load(fp: &str) -> Result<Self> {
+        let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+        serde_yaml::from_str(&text).with_context(|| format!("parse {} failed", fp))
+    }
+}
+
+/**
+ * 和声明不一致的地方
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    Missing,       // 没有这个容器
+    Image(String), // 镜像不一致，实际的镜像
+    State(String), // 运行状态不一致，实际的状态
+}
+
+#[derive(Debug, Clone)]
+pub struct ApReport {
+    pub mac: String,
+    pub containers: Vec<ContainerInfo>,
+    pub drift: Vec<(String, Drift)>,
+    pub failed: Vec<String>, // 操作失败的容器和原因
+    pub error: String,
+}
+
+/**
+ * 实际状态和声明比较
+ */
+pub fn drift(decl: &[ContainerDecl], actual: &[ContainerInfo]) -> Vec<(String, Drift)> {
+    decl.iter()
+        .filter_map(|d| {
+            let name = &d.spec.name;
+            let Some(c) = actual.iter().find(|c| &c.name == name) else {
+                return Some((name.clone(), Drift::Missing));
+            };
+            if !c.image.is_empty() && c.image != d.spec.image {
+                Some((name.clone(), Drift::Image(c.image.clone())))
+            } else if c.running() != d.running {
+                Some((name.clone(), Drift::State(c.status.clone())))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// 等到所有容器和声明一致，超时返回最后查到的状态
+async fn wait_state(
+    rpc: &SysCtrl,
+    decl: &[ContainerDecl],
+    timeout: u64,
+) -> Result<Vec<ContainerInfo>> {
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        let actual = rpc.container_status().await?;
+        let d = drift(decl, &actual);
+        if d.is_empty() || Instant::now() >= deadline {
+            return Ok(actual);
+        }
+        sleep(Duration::from_secs(WAIT_POLL)).await;
+    }
+}
+
+// 单个容器失败不影响其他容器，返回失败的说明
+async fn apply(
+    rpc: &SysCtrl,
+    action: ContainerAction,
+    decl: &[ContainerDecl],
+) -> Result<Vec<String>> {
+    let actual = rpc.container_status().await?;
+    let mut failed = Vec::new();
+    for d in decl {
+        let name = &d.spec.name;
+        let exists = actual.iter().any(|c| &c.name == name);
+        let ret = match action {
+            ContainerAction::Deploy if !exists => rpc.container_run(&d.spec).await,
+            ContainerAction::Deploy | ContainerAction::Update => {
+                rpc.container_update(&d.spec).await
+            }
+            // 声明为停止的不重启
+            ContainerAction::Restart if !d.running => continue,
+            ContainerAction::Restart => rpc.container_op("restart", name).await,
+            _ => continue,
+        };
+        if let Err(e) = ret {
+            warn!("{} {:?} {} failed, {}", rpc.name(), action, name, e);
+            failed.push(format!("{} {:?} failed, {}", name, action, e));
+            continue;
+        }
+        // 声明为停止的，部署后停掉
+        if !d.running && action != ContainerAction::Restart {
+            if let Err(e) = rpc.container_op("stop", name).await {
+                warn!("{} stop {} failed, {}", rpc.name(), name, e);
+                failed.push(format!("{} stop failed, {}", name, e));
+                continue;
+            }
+        }
+        info!("{} {:?} {}", rpc.name(), action, name);
+    }
+    Ok(failed)
+}
+
+async fn inspect(
+    cluster: &str,
+    mac: &str,
+    opt: &ContainerOpt,
+    decl: &[ContainerDecl],
+) -> Result<(Vec<ContainerInfo>, Vec<String>)> {
+    let rpc = SysCtrl::cluster(cluster, mac)?;
+    match opt.action {
+        ContainerAction::List | ContainerAction::Drift => {
+            Ok((rpc.container_status().await?, Vec::new()))
+        }
+        _ => {
+            let failed = apply(&rpc, opt.action, decl).await?;
+            Ok((wait_state(&rpc, decl, opt.timeout).await?, failed))
+        }
+    }
+}
+
+async fn handle_ap(
+    cluster: String,
+    mac: String,
+    opt: ContainerOpt,
+    decl: Vec<ContainerDecl>,
+) -> ApReport {
+    let mut report = ApReport {
+        mac: mac.clone(),
+        containers: Vec::new(),
+        drift: Vec::new(),
+        failed: Vec::new(),
+        error: String::new(),
+    };
+    match inspect(&cluster, &mac, &opt, &decl).await {
+        Ok((actual, failed)) => {
+            report.drift = drift(&decl, &actual);
+            report.containers = actual;
+            report.failed = failed;
+        }
+        Err(e) => report.error = e.to_string(),
+    }
+    report
+}
+
+/**
+ * 报告，list 列出容器，其他列出差异
+ */
+pub fn render(action: ContainerAction, reports: &[ApReport]) -> String {
+    let mut out = String::new();
+    for r in reports {
+        if !r.error.is_empty() {
+            out.push_str(&format!("{:<18} error {}\n", r.mac, r.error));
+            continue;
+        }
+        if action == ContainerAction::List {
+            for c in &r.containers {
+                out.push_str(&format!(
+                    "{:<18} {:<16} {:<28} {}\n",
+                    r.mac, c.name, c.image, c.status
+                ));
+            }
+        }
+        for (name, d) in &r.drift {
+            out.push_str(&format!("{:<18} {:<16} drift {:?}\n", r.mac, name, d));
+        }
+        for f in &r.failed {
+            out.push_str(&format!("{:<18} {}\n", r.mac, f));
+        }
+    }
+    let clean = reports
+        .iter()
+        .filter(|r| r.error.is_empty() && r.drift.is_empty() && r.failed.is_empty())
+        .count();
+    out.push_str(&format!("{}/{} ap match spec\n", clean, reports.len()));
+    out
+}
+
+/**
+ * container 子命令
+ */
+pub async fn run(opt: &ContainerOpt) -> Result<()> {
+    // 集群的设备路径用小写 mac
+    let aps: Vec<String> = load_aps(&opt.aps)?
+        .iter()
+        .map(|m| m.to_lowercase())
+        .collect();
+    // list 不用声明文件
+    let decl = if opt.action == ContainerAction::List {
+        Vec::new()
+    } else {
+        let mut decl = SpecFile::load(&opt.spec)?.containers;
+        if !opt.name.is_empty() {
+            decl.retain(|d| opt.name.contains(&d.spec.name));
+        }
+        if decl.is_empty() {
+            return Err(anyhow!("no container to handle in {}", opt.spec));
+        }
+        decl
+    };
+    let sem = Arc::new(Semaphore::new(opt.concurrency.max(1)));
+    let mut set = JoinSet::new();
+    for mac in aps {
+        let (sem, opt, decl) = (sem.clone(), opt.clone(), decl.clone());
+        set.spawn(async move {
+            let _permit = sem.acquire_owned().await;
+            handle_ap(opt.cluster.clone(), mac, opt, decl).await
+        });
+    }
+    let mut reports = Vec::new();
+    while let Some(r) = set.join_next().await {
+        match r {
+            Ok(r) => reports.push(r),
+            Err(e) => warn!("container task failed, {}", e),
+        }
+    }
+    reports.sort_by(|a, b| a.mac.cmp(&b.mac));
+    let text = render(opt.action, &reports);
+    print!("{}", text);
+    fs::write(&opt.out, text).with_context(|| format!("write {} failed", opt.out))?;
+    Ok(())
+}
@@ -1,7 +1,19 @@
 pub mod web;
-pub mod prdservice;
 pub mod logfile;
 pub mod uart;
+pub mod reset;
+pub mod bind;
+pub mod session;
+pub mod asbind;
+pub mod upgrade;
+pub mod serve;
+pub mod soak;
+pub mod sysctrl;
+pub mod scenario;
+pub mod container;
+pub mod wifi;
+pub mod aplog;
+pub mod snapshot;
 
 
 pub fn add(left: usize, right: usize) -> usize {
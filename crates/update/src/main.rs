@@ -1,12 +1,73 @@
-
-
 use anyhow_ext::{Ok, Result};
-use update::web::upgrade_ap;
+use structopt::StructOpt;
+use update::aplog::{self, LogOpt};
+use update::asbind::{self, LcdOpt};
+use update::bind::{self, BindOpt};
+use update::container::{self, ContainerOpt};
+use update::logfile::log_init_console;
+use update::reset::{self, ResetOpt};
+use update::scenario::{self, ScenarioOpt};
+use update::serve::{self, ServeOpt};
+use update::snapshot::{self, SnapshotOpt};
+use update::soak::{self, SoakOpt};
+use update::upgrade::{self, UpgradeOpt};
+use update::web::{self, upgrade_ap, CgiOpt};
+use update::wifi::{self, WifiOpt};
 
+#[derive(StructOpt)]
+#[structopt(name = "update", about = "ap upgrade and esl maintenance tools.")]
+struct Opt {
+    #[structopt(subcommand)]
+    cmd: Option<Cmd>,
+}
 
+#[derive(StructOpt)]
+enum Cmd {
+    /// 升级基站，不带子命令时的默认动作
+    UpgradeAp,
+    /// 按列表分波升级基站
+    Upgrade(UpgradeOpt),
+    /// 价签解绑、清屏或推送默认模版
+    Reset(ResetOpt),
+    /// 价签和商品绑定、解绑、校验
+    Bind(BindOpt),
+    /// 价签和 aurora lcd 循环更新
+    Lcd(LcdOpt),
+    /// 提供固件下载，支持断点续传
+    Serve(ServeOpt),
+    /// 两个版本之间循环升降级，检查版本和健康状态
+    Soak(SoakOpt),
+    /// 按场景文件调用 sysctrl 接口并校验返回
+    Scenario(ScenarioOpt),
+    /// 按声明文件管理基站上的容器
+    Container(ContainerOpt),
+    /// 循环切换 wifi 配置并检查
+    Wifi(WifiOpt),
+    /// 列出、上传并下载基站日志
+    Log(LogOpt),
+    /// 基站配置快照、比较和恢复
+    Snapshot(SnapshotOpt),
+    /// 读取、修改并写回基站的网络和 ew 连接参数
+    Cgi(CgiOpt),
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    upgrade_ap().await?;
+    log_init_console();
+    match Opt::from_args().cmd {
+        None | Some(Cmd::UpgradeAp) => upgrade_ap().await?,
+        Some(Cmd::Upgrade(opt)) => upgrade::run(&opt).await?,
+        Some(Cmd::Reset(opt)) => reset::run(&opt).await?,
+        Some(Cmd::Bind(opt)) => bind::run(&opt).await?,
+        Some(Cmd::Lcd(opt)) => asbind::run(&opt).await?,
+        Some(Cmd::Serve(opt)) => serve::run(&opt).await?,
+        Some(Cmd::Soak(opt)) => soak::run(&opt).await?,
+        Some(Cmd::Scenario(opt)) => scenario::run(&opt).await?,
+        Some(Cmd::Container(opt)) => container::run(&opt).await?,
+        Some(Cmd::Wifi(opt)) => wifi::run(&opt).await?,
+        Some(Cmd::Log(opt)) => aplog::run(&opt).await?,
+        Some(Cmd::Snapshot(opt)) => snapshot::run(&opt).await?,
+        Some(Cmd::Cgi(opt)) => web::cgi(&opt).await?,
+    }
     Ok(())
 }
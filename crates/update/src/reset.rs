@@ -0,0 +1,340 @@
+use anyhow_ext::{anyhow, Context, Result};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use chrono::Local;
+use forever::eslog::{EslAction, EslLog};
+use forever::pic::make_blank_pic;
+use log::{info, warn};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+
+/**
+ * 重置方式
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResetMode {
+    Unbind,   // UNBIND 模版，解绑
+    Blank,    // 推送纯白图片
+    Template, // 推送指定的默认模版
+}
+
+impl FromStr for ResetMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "unbind" => Ok(Self::Unbind),
+            "blank" => Ok(Self::Blank),
+            "template" => Ok(Self::Template),
+            _ => Err(format!("unknown mode {}, use unbind/blank/template", s)),
+        }
+    }
+}
+
+/**
+ * 确认价签重置完成的方式
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Confirm {
+    Log,      // 读 eslworking.log
+    Callback, // 本地监听 ew 回调
+    None,     // 只看下发接口返回
+}
+
+impl FromStr for Confirm {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(Self::Log),
+            "callback" => Ok(Self::Callback),
+            "none" => Ok(Self::None),
+            _ => Err(format!("unknown confirm {}, use log/callback/none", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ResetOpt {
+    /// ew api 地址
+    #[structopt(long, default_value = "127.0.0.1:9000")]
+    pub api: String,
+
+    /// usercode
+    #[structopt(long, default_value = "god.2")]
+    pub uc: String,
+
+    /// 价签列表，一行一个，可以带 eslid= 前缀
+    #[structopt(long, default_value = "esl.txt")]
+    pub esl: String,
+
+    /// 重置方式 unbind/blank/template
+    #[structopt(long, default_value = "unbind")]
+    pub mode: ResetMode,
+
+    /// template 方式推送的模版
+    #[structopt(long, default_value = "DEFAULT")]
+    pub template: String,
+
+    /// blank 方式的图片宽高
+    #[structopt(long, default_value = "296")]
+    pub width: u32,
+    #[structopt(long, default_value = "128")]
+    pub height: u32,
+
+    /// 每批下发的价签数
+    #[structopt(long, default_value = "200")]
+    pub batch: usize,
+
+    /// ew 回调地址，callback 确认时要指向 listen
+    #[structopt(long, default_value = "")]
+    pub back_url: String,
+
+    /// 确认方式 log/callback/none
+    #[structopt(long, default_value = "log")]
+    pub confirm: Confirm,
+
+    /// log 确认时读取的 ew 日志
+    #[structopt(long, default_value = "/data/eslw/log/eslworking.log")]
+    pub ewlog: String,
+
+    /// callback 确认时监听的地址
+    #[structopt(long, default_value = "0.0.0.0:8083")]
+    pub listen: String,
+
+    /// 等待确认的时间，单位为秒
+    #[structopt(long, default_value = "600")]
+    pub wait: u64,
+
+    /// 没有重置成功的价签写入这个文件
+    #[structopt(long, default_value = "reset_failed.txt")]
+    pub out: String,
+}
+
+/**
+ * 每个价签的确认结果
+ */
+#[derive(Debug, Default)]
+struct Tracker {
+    pending: BTreeSet<String>,
+    done: BTreeSet<String>,
+    failed: BTreeMap<String, String>, // 价签 -> 原因
+}
+
+type SharedTracker = Arc<Mutex<Tracker>>;
+
+impl Tracker {
+    // 只处理本次下发的价签，成功之后不再改
+    fn mark(&mut self, esl: &str, status: &str) {
+        if !self.pending.remove(esl) && !self.failed.contains_key(esl) {
+            return;
+        }
+        if status.is_empty() || status == "success" {
+            self.failed.remove(esl);
+            self.done.insert(esl.to_string());
+        } else {
+            self.failed.insert(esl.to_string(), status.to_string());
+        }
+    }
+}
+
+/**
+ ** 获取制定的文件内容，并去标eslid=开头
+ */
+pub fn get_esl(fp: &str) -> Vec<String> {
+    let mut esl_list = Vec::new();
+    if let Ok(contents) = std::fs::read_to_string(fp) {
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            esl_list.push(line.strip_prefix("eslid=").unwrap_or(line).to_owned());
+        }
+    }
+
+    esl_list
+}
+
+/**
+ * 单个价签的下发数据
+ */
+fn update_data(opt: &ResetOpt, sid: &str, esl: &str, image: &Option<String>) -> Value {
+    let template = match opt.mode {
+        ResetMode::Unbind => "UNBIND",
+        _ => opt.template.as_str(),
+    };
+    match opt.mode {
+        ResetMode::Unbind | ResetMode::Template => json!({
+            "sid": sid,
+            "priority": 10,
+            "esl_id": esl,
+            "back_url": opt.back_url,
+            "template": template,
+        }),
+        ResetMode::Blank => json!({
+            "sid": sid,
+            "priority": 10,
+            "esl_id": esl,
+            "back_url": opt.back_url,
+            "screen": {
+                "name": esl,
+                "default_page": "normal",
+                "default_page_id": "0",
+                "pages": [{"id": 0, "name": "normal", "image": image}]
+            },
+        }),
+    }
+}
+
+// ew 回调，单个或数组 {"sid","esl_id","status"}
+async fn on_callback(State(tracker): State<SharedTracker>, Json(body): Json<Value>) -> Json<Value> {
+    let items = match body {
+        Value::Array(v) => v,
+        v => vec![v],
+    };
+    let mut t = tracker.lock().unwrap();
+    for item in items {
+        if let Some(esl) = item["esl_id"].as_str() {
+            t.mark(esl, item["status"].as_str().unwrap_or(""));
+        }
+    }
+    Json(json!({"error_code": 0}))
+}
+
+async fn listen_callback(addr: &str, tracker: SharedTracker) -> Result<()> {
+    let app = Router::new()
+        .fallback(post(on_callback))
+        .with_state(tracker);
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("listen {} failed", addr))?;
+    info!("reset callback listen on {}", addr);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("callback server stopped, {}", e);
+        }
+    });
+    Ok(())
+}
+
+// 从上次位置读新写入的日志，记下结束的价签
+fn read_log(fp: &str, seek: &mut u64, esl_log: &EslLog, tracker: &SharedTracker) -> Result<()> {
+    let mut t = tracker.lock().unwrap();
+    esl_log.tail(fp, seek, |ev| {
+        if let EslAction::Finish(status) = ev.action {
+            t.mark(&ev.eslid, &status);
+        }
+    })?;
+    Ok(())
+}
+
+/**
+ * 重置价签：分批下发，按 confirm 等待确认，返回没有重置成功的价签
+ */
+pub async fn reset(opt: &ResetOpt) -> Result<BTreeMap<String, String>> {
+    let esl_list = get_esl(&opt.esl);
+    if esl_list.is_empty() {
+        return Err(anyhow!("no esl in {}", opt.esl));
+    }
+    if opt.confirm == Confirm::Callback && opt.back_url.is_empty() {
+        return Err(anyhow!("callback confirm need --back-url"));
+    }
+    info!("reset {} esl, mode={:?}", esl_list.len(), opt.mode);
+
+    let tracker = SharedTracker::default();
+    tracker.lock().unwrap().pending = esl_list.iter().cloned().collect();
+    let esl_log = EslLog::new();
+    let mut seek = 0;
+    match opt.confirm {
+        Confirm::Log => seek = fs::metadata(&opt.ewlog)?.len(),
+        Confirm::Callback => listen_callback(&opt.listen, tracker.clone()).await?,
+        Confirm::None => {}
+    }
+
+    let image = (opt.mode == ResetMode::Blank).then(|| make_blank_pic(opt.width, opt.height));
+    let sid = Local::now().format("%Y%m%d%H%M%S%3f").to_string();
+    let url = format!("http://{}/api3/{}/esls", opt.api, opt.uc);
+    let client = Client::new();
+    for chunk in esl_list.chunks(opt.batch.max(1)) {
+        let data: Vec<Value> = chunk
+            .iter()
+            .map(|e| update_data(opt, &sid, e, &image))
+            .collect();
+        let res = client
+            .put(&url)
+            .timeout(Duration::from_secs(10))
+            .json(&json!({ "data": data }))
+            .send()
+            .await;
+        let reason = match res {
+            Ok(r) if r.status().is_success() => None,
+            Ok(r) => Some(format!("http {}", r.status())),
+            Err(e) => Some(format!("request failed, {}", e)),
+        };
+        if let Some(reason) = reason {
+            warn!("reset batch of {} failed, {}", chunk.len(), reason);
+            let mut t = tracker.lock().unwrap();
+            for e in chunk {
+                t.pending.remove(e);
+                t.failed.insert(e.clone(), reason.clone());
+            }
+        } else {
+            info!("reset batch of {} sent", chunk.len());
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+
+    if opt.confirm == Confirm::None {
+        let mut t = tracker.lock().unwrap();
+        let pending = std::mem::take(&mut t.pending);
+        t.done.extend(pending);
+    }
+    let deadline = Instant::now() + Duration::from_secs(opt.wait);
+    while Instant::now() < deadline {
+        if tracker.lock().unwrap().pending.is_empty() {
+            break;
+        }
+        sleep(Duration::from_secs(1)).await;
+        if opt.confirm == Confirm::Log {
+            if let Err(e) = read_log(&opt.ewlog, &mut seek, &esl_log, &tracker) {
+                warn!("read {} failed, {}", opt.ewlog, e);
+            }
+        }
+    }
+
+    let mut t = tracker.lock().unwrap();
+    let pending = std::mem::take(&mut t.pending);
+    for e in pending {
+        t.failed.insert(e, "timeout".to_string());
+    }
+    info!(
+        "reset finish, done={}, failed={}",
+        t.done.len(),
+        t.failed.len()
+    );
+    Ok(std::mem::take(&mut t.failed))
+}
+
+/**
+ * reset 子命令，输出并保存没有重置成功的价签
+ */
+pub async fn run(opt: &ResetOpt) -> Result<()> {
+    let failed = reset(opt).await?;
+    for (e, reason) in &failed {
+        println!("{:<14} {}", e, reason);
+    }
+    let text: String = failed.keys().map(|e| format!("{}\n", e)).collect();
+    fs::write(&opt.out, text).with_context(|| format!("write {} failed", opt.out))?;
+    println!("{} esl not reset, write to {}", failed.len(), opt.out);
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use crate::sysctrl::SysCtrl;
+use anyhow_ext::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ScenarioOpt {
+    /// 场景文件，yaml 或 json
+    pub file: String,
+
+    /// 基站 mac，填了覆盖场景里的 devices
+    #[structopt(long)]
+    pub device: Vec<String>,
+
+    /// 集群地址，填了覆盖场景里的 cluster
+    #[structopt(long)]
+    pub cluster: Option<String>,
+
+    /// 报告写入这个文件
+    #[structopt(long, default_value = "scenario_report.txt")]
+    pub out: String,
+}
+
+/**
+ * 一个步骤，expect 的键是返回里的路径，如 data.list.0.name
+ * 返回 code 不为 0 时步骤直接失败，不用在 expect 里写 code
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    pub name: Option<String>,
+    pub path: String, // sysctrl 之后的部分，如 conf/net_get
+    #[serde(default)]
+    pub body: Value,
+    pub delay: Option<u64>, // 执行后等待的秒数，不填用场景的 delay
+    #[serde(default)]
+    pub expect: BTreeMap<String, Value>,
+}
+
+impl Step {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.path)
+    }
+}
+
+/**
+ * 场景文件，cluster 和 local 二选一
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    pub cluster: Option<String>,
+    pub local: Option<String>, // 直接连基站，填基站 ip
+    #[serde(default)]
+    pub devices: Vec<String>,
+    #[serde(default)]
+    pub delay: u64,
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    /// yaml 兼容 json，两种都按 yaml 读
+    pub fn load(fp: &str) -> Result<Self> {
+        let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+        let sc: Scenario =
+            serde_yaml::from_str(&text).with_context(|| format!("parse {} failed", fp))?;
+        if sc.steps.is_empty() {
+            return Err(anyhow!("{} has no steps", fp));
+        }
+        Ok(sc)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub device: String,
+    pub step: String,
+    pub pass: bool,
+    pub ms: u128,
+    pub error: String,
+}
+
+/**
+ * 按 a.b.0.c 取值，数字段当数组下标
+ */
+pub fn lookup<'a>(v: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|p| !p.is_empty())
+        .try_fold(v, |v, key| match v {
+            Value::Array(list) => list.get(key.parse::<usize>().ok()?),
+            _ => v.get(key),
+        })
+}
+
+/**
+ * 检查返回，"*" 表示字段存在即可，返回不符合的说明
+ */
+pub fn check(resp: &Value, expect: &BTreeMap<String, Value>) -> Vec<String> {
+    expect
+        .iter()
+        .filter_map(|(path, want)| match (lookup(resp, path), want) {
+            (Some(_), Value::String(s)) if s == "*" => None,
+            (Some(got), want) if got == want => None,
+            (got, want) => Some(format!(
+                "{}: expect {}, got {}",
+                path,
+                want,
+                got.map(|g| g.to_string()).unwrap_or("none".to_string())
+            )),
+        })
+        .collect()
+}
+
+async fn run_device(rpc: SysCtrl, sc: Scenario) -> Vec<StepResult> {
+    let mut results = Vec::new();
+    for step in &sc.steps {
+        let start = Instant::now();
+        // 脚本里不带参数的也是发 {}
+        let body = match &step.body {
+            Value::Null => json!({}),
+            b => b.clone(),
+        };
+        let errors = match rpc.call::<_, Value>(&step.path, &body).await {
+            Ok(reply) => {
+                // 没有 data 的不放这个键，expect 里 data: "*" 才能查出来
+                let mut resp = json!({"code": reply.code, "msg": reply.msg});
+                if let Some(data) = reply.data {
+                    resp["data"] = data;
+                }
+                check(&resp, &step.expect)
+            }
+            Err(e) => vec![e.to_string()],
+        };
+        let r = StepResult {
+            device: rpc.name().to_string(),
+            step: step.name().to_string(),
+            pass: errors.is_empty(),
+            ms: start.elapsed().as_millis(),
+            error: errors.join("; "),
+        };
+        if r.pass {
+            info!("{} {} pass, {}ms", r.device, r.step, r.ms);
+        } else {
+            warn!("{} {} failed, {}", r.device, r.step, r.error);
+        }
+        results.push(r);
+        sleep(Duration::from_secs(step.delay.unwrap_or(sc.delay))).await;
+    }
+    results
+}
+
+/**
+ * 每台基站并发跑，同一台按顺序执行步骤
+ */
+pub async fn run_scenario(sc: &Scenario) -> Result<Vec<StepResult>> {
+    let mut targets = Vec::new();
+    match (&sc.cluster, &sc.local) {
+        (Some(cluster), _) => {
+            if sc.devices.is_empty() {
+                return Err(anyhow!("no devices for cluster {}", cluster));
+            }
+            for mac in &sc.devices {
+                targets.push(SysCtrl::cluster(cluster, mac)?);
+            }
+        }
+        (None, Some(host)) => targets.push(SysCtrl::local(host)?),
+        (None, None) => return Err(anyhow!("scenario needs cluster or local")),
+    }
+    let mut set = JoinSet::new();
+    for rpc in targets {
+        set.spawn(run_device(rpc, sc.clone()));
+    }
+    let mut results = Vec::new();
+    while let Some(r) = set.join_next().await {
+        match r {
+            Ok(r) => results.extend(r),
+            Err(e) => warn!("scenario task failed, {}", e),
+        }
+    }
+    results.sort_by(|a, b| a.device.cmp(&b.device));
+    Ok(results)
+}
+
+/**
+ * 报告
+ */
+pub fn render(name: &str, results: &[StepResult]) -> String {
+    let mut out = format!(
+        "{:<20} {:<28} {:<6} {:>6} {}\n",
+        "device", "step", "result", "ms", "error"
+    );
+    for r in results {
+        out.push_str(&format!(
+            "{:<20} {:<28} {:<6} {:>6} {}\n",
+            r.device,
+            r.step,
+            if r.pass { "pass" } else { "FAIL" },
+            r.ms,
+            r.error
+        ));
+    }
+    let pass = results.iter().filter(|r| r.pass).count();
+    out.push_str(&format!(
+        "scenario {}: {}/{} steps pass\n",
+        name,
+        pass,
+        results.len()
+    ));
+    out
+}
+
+/**
+ * scenario 子命令，有失败的步骤时返回错误
+ */
+pub async fn run(opt: &ScenarioOpt) -> Result<()> {
+    let mut sc = Scenario::load(&opt.file)?;
+    if !opt.device.is_empty() {
+        sc.devices = opt.device.clone();
+    }
+    if opt.cluster.is_some() {
+        sc.cluster = opt.cluster.clone();
+    }
+    let results = run_scenario(&sc).await?;
+    let report = render(&sc.name, &results);
+    print!("{}", report);
+    fs::write(&opt.out, &report).with_context(|| format!("write {} failed", opt.out))?;
+    let failed = results.iter().filter(|r| !r.pass).count();
+    if failed > 0 {
+        return Err(anyhow!("{} steps failed, see {}", failed, opt.out));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, lookup};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_check() {
+        let resp = json!({"code": 0, "data": {"list": [{"name": "fastfs", "status": "running"}]}});
+        assert_eq!(lookup(&resp, "data.list.0.name"), Some(&json!("fastfs")));
+        assert_eq!(lookup(&resp, "data.list.1.name"), None);
+
+        let mut expect = BTreeMap::new();
+        expect.insert("code".to_string(), json!(0));
+        expect.insert("data.list.0.status".to_string(), json!("*"));
+        assert!(check(&resp, &expect).is_empty());
+        expect.insert("data.list.0.name".to_string(), json!("aoa-client"));
+        assert_eq!(check(&resp, &expect).len(), 1);
+    }
+}
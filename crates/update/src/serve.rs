@@ -0,0 +1,307 @@
+use anyhow_ext::{Context, Result};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::{Component, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use std::time::SystemTime;
+use structopt::StructOpt;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, ReadBuf, Take};
+use tokio::net::TcpListener;
+use tokio_util::io::ReaderStream;
+
+/// 下载进度每隔这么多百分比打一次日志
+const PROGRESS_STEP: u64 = 10;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ServeOpt {
+    /// 监听地址
+    #[structopt(long, default_value = "0.0.0.0:8080")]
+    pub addr: String,
+
+    /// 固件目录，按 /blob/<文件名> 访问
+    #[structopt(long, default_value = "images")]
+    pub dir: String,
+}
+
+/**
+ * 固件文件服务，md5 按修改时间缓存
+ */
+struct ImageServer {
+    dir: PathBuf,
+    md5: Mutex<HashMap<PathBuf, (SystemTime, String)>>,
+}
+
+type SharedServer = Arc<ImageServer>;
+
+impl ImageServer {
+    // 不允许 .. 之类跳出固件目录
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let rel = PathBuf::from(path);
+        if rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            Some(self.dir.join(rel))
+        } else {
+            None
+        }
+    }
+
+    async fn md5(&self, fp: &PathBuf, modified: SystemTime) -> Result<String> {
+        if let Some((t, sum)) = self.md5.lock().unwrap().get(fp) {
+            if *t == modified {
+                return Ok(sum.clone());
+            }
+        }
+        let path = fp.clone();
+        let sum = tokio::task::spawn_blocking(move || -> Result<String> {
+            let mut file = std::fs::File::open(&path)?;
+            let mut ctx = md5::Context::new();
+            let mut buf = vec![0u8; 1 << 20];
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                ctx.consume(&buf[..n]);
+            }
+            Ok(format!("{:x}", ctx.compute()))
+        })
+        .await??;
+        self.md5
+            .lock()
+            .unwrap()
+            .insert(fp.clone(), (modified, sum.clone()));
+        Ok(sum)
+    }
+}
+
+/**
+ * Range 头的解析结果
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    Ignore,         // 格式不对或不支持，按整个文件返回 200
+    Part(u64, u64), // 闭区间，返回 206
+    Unsatisfiable,  // 区间在文件外，返回 416
+}
+
+/**
+ * 解析 Range: bytes=a-b / a- / -n，只支持单个区间，多区间按不支持忽略
+ */
+pub fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Ignore;
+    };
+    if spec.contains(',') {
+        return ByteRange::Ignore;
+    }
+    let Some((a, b)) = spec.split_once('-') else {
+        return ByteRange::Ignore;
+    };
+    let num = |s: &str| s.trim().parse::<u64>().ok();
+    if a.trim().is_empty() {
+        return match num(b) {
+            None => ByteRange::Ignore,
+            Some(0) => ByteRange::Unsatisfiable,
+            Some(_) if len == 0 => ByteRange::Unsatisfiable,
+            Some(n) => ByteRange::Part(len.saturating_sub(n), len - 1),
+        };
+    }
+    let Some(start) = num(a) else {
+        return ByteRange::Ignore;
+    };
+    let end = match b.trim() {
+        "" => None,
+        e => match num(e) {
+            Some(e) if e >= start => Some(e),
+            _ => return ByteRange::Ignore,
+        },
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Part(start, end.map_or(len - 1, |e| e.min(len - 1)))
+}
+
+/**
+ * 统计发送的字节，按百分比打日志
+ */
+struct Progress {
+    inner: Take<File>,
+    peer: SocketAddr,
+    name: String,
+    offset: u64, // 区间起点
+    sent: u64,
+    total: u64, // 文件大小
+    logged: u64,
+}
+
+impl AsyncRead for Progress {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let ret = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = ret {
+            let n = (buf.filled().len() - before) as u64;
+            self.sent += n;
+            let pos = self.offset + self.sent;
+            let percent = pos * 100 / self.total.max(1);
+            if percent >= self.logged + PROGRESS_STEP || (n == 0 && percent > self.logged) {
+                self.logged = percent - percent % PROGRESS_STEP;
+                info!(
+                    "{} download {} {}% ({}/{})",
+                    self.peer, self.name, percent, pos, self.total
+                );
+            }
+        }
+        ret
+    }
+}
+
+async fn get_blob(
+    State(st): State<SharedServer>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(fp) = st.resolve(&path) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let mut file = match File::open(&fp).await {
+        Ok(f) => f,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let meta = match file.metadata().await {
+        Ok(m) if m.is_file() => m,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let len = meta.len();
+    let md5 = match st
+        .md5(&fp, meta.modified().unwrap_or(SystemTime::UNIX_EPOCH))
+        .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("md5 {:?} failed, {}", fp, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(ByteRange::Ignore, |v| parse_range(v, len));
+    let (status, start, end) = match range {
+        ByteRange::Ignore => (StatusCode::OK, 0, len.saturating_sub(1)),
+        ByteRange::Part(s, e) => (StatusCode::PARTIAL_CONTENT, s, e),
+        ByteRange::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+            )
+                .into_response()
+        }
+    };
+    let count = if len == 0 { 0 } else { end - start + 1 };
+    if file.seek(std::io::SeekFrom::Start(start)).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    info!("{} get {} bytes {}-{} of {}", peer, path, start, end, len);
+
+    let body = Progress {
+        inner: file.take(count),
+        peer,
+        name: path,
+        offset: start,
+        sent: 0,
+        total: len,
+        logged: start * 100 / len.max(1),
+    };
+    let mut resp = Response::new(Body::from_stream(ReaderStream::new(body)));
+    *resp.status_mut() = status;
+    let h = resp.headers_mut();
+    h.insert(header::CONTENT_LENGTH, count.into());
+    h.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+    h.insert(header::ETAG, format!("\"{}\"", md5).parse().unwrap());
+    h.insert("x-checksum-md5", md5.parse().unwrap());
+    if status == StatusCode::PARTIAL_CONTENT {
+        h.insert(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, len).parse().unwrap(),
+        );
+    }
+    resp
+}
+
+/**
+ * 启动固件服务，在后台运行
+ */
+pub async fn start(opt: &ServeOpt) -> Result<SocketAddr> {
+    let st = Arc::new(ImageServer {
+        dir: PathBuf::from(&opt.dir),
+        md5: Mutex::new(HashMap::new()),
+    });
+    let app = Router::new()
+        .route("/blob/{*path}", get(get_blob))
+        .with_state(st);
+    let listener = TcpListener::bind(&opt.addr)
+        .await
+        .with_context(|| format!("listen {} failed", opt.addr))?;
+    let addr = listener.local_addr()?;
+    info!("serve {} on http://{}/blob/", opt.dir, addr);
+    tokio::spawn(async move {
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("image server stopped, {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+/**
+ * serve 子命令，一直运行
+ */
+pub async fn run(opt: &ServeOpt) -> Result<()> {
+    start(opt).await?;
+    std::future::pending::<()>().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range, ByteRange};
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Part(0, 99));
+        assert_eq!(parse_range("bytes=900-", 1000), ByteRange::Part(900, 999));
+        assert_eq!(parse_range("bytes=-100", 1000), ByteRange::Part(900, 999));
+        assert_eq!(parse_range("bytes=-2000", 1000), ByteRange::Part(0, 999));
+        assert_eq!(
+            parse_range("bytes=500-2000", 1000),
+            ByteRange::Part(500, 999)
+        );
+        // 区间在文件外
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-9", 0), ByteRange::Unsatisfiable);
+        // 格式不对或不支持，返回整个文件
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), ByteRange::Ignore);
+        assert_eq!(parse_range("items=0-1", 1000), ByteRange::Ignore);
+        assert_eq!(parse_range("bytes=9-5", 1000), ByteRange::Ignore);
+        assert_eq!(parse_range("bytes=a-", 1000), ByteRange::Ignore);
+        assert_eq!(parse_range("bytes=-", 1000), ByteRange::Ignore);
+    }
+}
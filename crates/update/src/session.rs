@@ -0,0 +1,176 @@
+use anyhow_ext::{anyhow, Context, Result};
+use log::{info, warn};
+use reqwest::{header, Client, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36";
+
+/// 登录返回没有 expires_in 时 token 的有效期，单位为秒
+const TOKEN_TTL: u64 = 3600;
+
+/// 提前这么多秒刷新 token
+const TOKEN_MARGIN: u64 = 60;
+
+/// 默认的平台配置文件
+pub const AUTH_FILE: &str = "platform.json";
+
+/**
+ * 平台地址和账号，先读配置文件，环境变量 ALLSTAR_SERVER / ALLSTAR_USER / ALLSTAR_PASSWORD 覆盖
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConf {
+    pub server: String,   // 代理服务地址 http://host:8084
+    pub user: String,     // allstar 用户
+    pub password: String, // md5 之后的密码
+}
+
+impl AuthConf {
+    pub fn load(fp: &str) -> Result<Self> {
+        let mut conf = if Path::new(fp).exists() {
+            let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+            serde_json::from_str(&text).with_context(|| format!("parse {} failed", fp))?
+        } else {
+            Self::default()
+        };
+        for (key, field) in [
+            ("ALLSTAR_SERVER", &mut conf.server),
+            ("ALLSTAR_USER", &mut conf.user),
+            ("ALLSTAR_PASSWORD", &mut conf.password),
+        ] {
+            if let Ok(v) = env::var(key) {
+                *field = v;
+            }
+        }
+        if conf.server.is_empty() || conf.user.is_empty() || conf.password.is_empty() {
+            return Err(anyhow!(
+                "server/user/password not set, use {} or ALLSTAR_* env",
+                fp
+            ));
+        }
+        conf.server = conf.server.trim_end_matches('/').to_string();
+        Ok(conf)
+    }
+}
+
+#[derive(Debug)]
+struct Token {
+    value: String,
+    expires: Instant,
+}
+
+/**
+ * allstar 登录会话，缓存 access_token，过期或 401 时重新登录
+ */
+#[derive(Debug)]
+pub struct Session {
+    conf: AuthConf,
+    client: Client,
+    token: Mutex<Option<Token>>,
+}
+
+pub type SharedSession = Arc<Session>;
+
+impl Session {
+    pub fn new(conf: AuthConf) -> SharedSession {
+        Arc::new(Self {
+            conf,
+            client: Client::new(),
+            token: Mutex::new(None),
+        })
+    }
+
+    /// 读取配置并创建会话，第一次请求时才登录
+    pub fn load(fp: &str) -> Result<SharedSession> {
+        Ok(Self::new(AuthConf::load(fp)?))
+    }
+
+    /// 代理服务下的完整地址
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.conf.server, path.trim_start_matches('/'))
+    }
+
+    async fn login(&self) -> Result<Token> {
+        let info: Value = self
+            .client
+            .post(self.url("proxy/allstar/user/login"))
+            .header(header::USER_AGENT, USER_AGENT)
+            .json(&json!({"username": self.conf.user, "password": self.conf.password}))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let data = &info["data"];
+        let value = data["access_token"]
+            .as_str()
+            .ok_or(anyhow!("login failed, {}", info["msg"]))?
+            .to_string();
+        let ttl = data["expires_in"].as_u64().unwrap_or(TOKEN_TTL);
+        info!("allstar login as {}, token ttl {}s", self.conf.user, ttl);
+        Ok(Token {
+            value,
+            expires: Instant::now() + Duration::from_secs(ttl.saturating_sub(TOKEN_MARGIN)),
+        })
+    }
+
+    /// 缓存的 token，没有或快过期时重新登录
+    pub async fn token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(t) = token.as_ref() {
+            if Instant::now() < t.expires {
+                return Ok(t.value.clone());
+            }
+        }
+        let t = self.login().await?;
+        let value = t.value.clone();
+        *token = Some(t);
+        Ok(value)
+    }
+
+    async fn invalidate(&self) {
+        *self.token.lock().await = None;
+    }
+
+    /// 带 token 的请求，401 时重新登录再试一次
+    pub async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value> {
+        let url = self.url(path);
+        for retry in [false, true] {
+            let mut req = self
+                .client
+                .request(method.clone(), &url)
+                .header(header::USER_AGENT, USER_AGENT)
+                .bearer_auth(self.token().await?);
+            if let Some(body) = body {
+                req = req.json(body);
+            }
+            let resp = req.send().await?;
+            let status = resp.status();
+            if status == StatusCode::UNAUTHORIZED && !retry {
+                warn!("{} unauthorized, login again", url);
+                self.invalidate().await;
+                continue;
+            }
+            let v: Value = resp.json().await.unwrap_or(Value::Null);
+            if !status.is_success() {
+                return Err(anyhow!("{} status {}, {}", url, status, v));
+            }
+            return Ok(v);
+        }
+        Err(anyhow!("{} unauthorized", url))
+    }
+
+    pub async fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        self.request(Method::POST, path, Some(body)).await
+    }
+
+    pub async fn get(&self, path: &str) -> Result<Value> {
+        self.request(Method::GET, path, None).await
+    }
+}
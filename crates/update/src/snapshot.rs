@@ -0,0 +1,340 @@
+use crate::sysctrl::SysCtrl;
+use crate::upgrade::load_aps;
+use anyhow_ext::{anyhow, Context, Result};
+use chrono::Local;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// 快照格式的版本，字段变化时加一
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// 快照包含的配置，读 conf/<name>_get，写 conf/<name>
+pub const SECTIONS: [&str; 8] = [
+    "vendor",
+    "server",
+    "net",
+    "web",
+    "wifi",
+    "timezone",
+    "internal_dhcp",
+    "dhcp_options",
+];
+
+/// 只读的配置，恢复时跳过
+const READ_ONLY: [&str; 1] = ["vendor"];
+
+/// 网络配置，写完基站可能换地址，恢复时放最后
+const NET: &str = "net";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotAction {
+    Capture,
+    Diff,
+    Restore,
+}
+
+impl FromStr for SnapshotAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "capture" => Ok(Self::Capture),
+            "diff" => Ok(Self::Diff),
+            "restore" => Ok(Self::Restore),
+            _ => Err(format!("unknown action {}, use capture/diff/restore", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct SnapshotOpt {
+    /// capture/diff/restore
+    pub action: SnapshotAction,
+
+    /// diff 两个快照文件，或 restore 的快照文件
+    pub files: Vec<String>,
+
+    /// 集群地址
+    #[structopt(long, default_value = "https://10.11.173.231:9900")]
+    pub cluster: String,
+
+    /// 基站 mac，可以填多个
+    #[structopt(long)]
+    pub mac: Vec<String>,
+
+    /// 基站 mac 列表文件，和 --mac 一起用
+    #[structopt(long)]
+    pub aps: Option<String>,
+
+    /// 快照保存目录
+    #[structopt(long, default_value = "snapshot")]
+    pub dir: String,
+
+    /// diff 时基站和这个标准配置比较
+    #[structopt(long)]
+    pub golden: Option<String>,
+
+    /// 只处理这些配置，不填为全部
+    #[structopt(long)]
+    pub section: Vec<String>,
+}
+
+/**
+ * 一台基站的配置快照
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub mac: String,
+    pub time: String,
+    pub conf: BTreeMap<String, Value>,
+}
+
+impl Snapshot {
+    /// 逐个读取配置，读失败的跳过
+    pub async fn capture(rpc: &SysCtrl, sections: &[&str]) -> Result<Self> {
+        let mut conf = BTreeMap::new();
+        for name in sections {
+            match rpc.conf_get(name).await {
+                Ok(v) => {
+                    conf.insert(name.to_string(), v);
+                }
+                Err(e) => warn!("{} {}_get failed, {}", rpc.name(), name, e),
+            }
+        }
+        if conf.is_empty() {
+            return Err(anyhow!("{} no conf captured", rpc.name()));
+        }
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            mac: rpc.name().to_string(),
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            conf,
+        })
+    }
+
+    pub fn load(fp: &str) -> Result<Self> {
+        let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+        let snap: Snapshot =
+            serde_json::from_str(&text).with_context(|| format!("parse {} failed", fp))?;
+        if snap.version > SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "{} version {} is newer than {}",
+                fp,
+                snap.version,
+                SNAPSHOT_VERSION
+            ));
+        }
+        Ok(snap)
+    }
+
+    /// 保存为 <dir>/<mac>_<时间>.json
+    pub fn save(&self, dir: &str) -> Result<String> {
+        fs::create_dir_all(dir).with_context(|| format!("create {} failed", dir))?;
+        let name = format!(
+            "{}_{}.json",
+            self.mac.replace(':', ""),
+            Local::now().format("%Y%m%d_%H%M%S")
+        );
+        let fp = Path::new(dir).join(name).to_string_lossy().to_string();
+        fs::write(&fp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("write {} failed", fp))?;
+        Ok(fp)
+    }
+
+    /// 写回基站，net 最后写，返回写失败的配置
+    pub async fn restore(&self, rpc: &SysCtrl, sections: &[&str]) -> Vec<String> {
+        let mut failed = Vec::new();
+        let ordered = self
+            .conf
+            .iter()
+            .filter(|(name, _)| name.as_str() != NET)
+            .chain(self.conf.get_key_value(NET));
+        for (name, v) in ordered {
+            if READ_ONLY.contains(&name.as_str()) || !sections.contains(&name.as_str()) {
+                continue;
+            }
+            match rpc.conf_set(name, v).await {
+                Ok(_) => info!("{} restore {}", rpc.name(), name),
+                Err(e) => {
+                    warn!("{} restore {} failed, {}", rpc.name(), name, e);
+                    failed.push(name.clone());
+                }
+            }
+        }
+        failed
+    }
+}
+
+// mac 不区分大小写和分隔符
+fn same_mac(a: &str, b: &str) -> bool {
+    let norm = |m: &str| m.replace([':', '-'], "").to_lowercase();
+    norm(a) == norm(b)
+}
+
+// 展开成 a.b.0 的路径
+fn flatten(prefix: &str, v: &Value, out: &mut BTreeMap<String, Value>) {
+    let key = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{}.{}", prefix, k)
+        }
+    };
+    match v {
+        Value::Object(map) => map.iter().for_each(|(k, v)| flatten(&key(k), v, out)),
+        Value::Array(list) => list
+            .iter()
+            .enumerate()
+            .for_each(|(i, v)| flatten(&key(&i.to_string()), v, out)),
+        v => {
+            out.insert(prefix.to_string(), v.clone());
+        }
+    }
+}
+
+/**
+ * 比较两份配置，返回 (路径, a 的值, b 的值)
+ */
+pub fn diff(
+    a: &BTreeMap<String, Value>,
+    b: &BTreeMap<String, Value>,
+) -> Vec<(String, Option<Value>, Option<Value>)> {
+    let (mut fa, mut fb) = (BTreeMap::new(), BTreeMap::new());
+    for (k, v) in a {
+        flatten(k, v, &mut fa);
+    }
+    for (k, v) in b {
+        flatten(k, v, &mut fb);
+    }
+    let mut keys: Vec<&String> = fa.keys().chain(fb.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|k| fa.get(*k) != fb.get(*k))
+        .map(|k| (k.clone(), fa.get(k).cloned(), fb.get(k).cloned()))
+        .collect()
+}
+
+fn print_diff(a: &str, b: &str, d: &[(String, Option<Value>, Option<Value>)]) {
+    println!("--- {}\n+++ {}", a, b);
+    for (k, va, vb) in d {
+        let show = |v: &Option<Value>| v.as_ref().map(|v| v.to_string()).unwrap_or("-".into());
+        println!("{:<40} {} -> {}", k, show(va), show(vb));
+    }
+    println!("{} differences", d.len());
+}
+
+/**
+ * snapshot 子命令
+ */
+pub async fn run(opt: &SnapshotOpt) -> Result<()> {
+    let sections: Vec<&str> = SECTIONS
+        .iter()
+        .copied()
+        .filter(|s| opt.section.is_empty() || opt.section.iter().any(|x| x == s))
+        .collect();
+    // 集群的设备路径用小写 mac
+    let mut macs: Vec<String> = opt.mac.iter().map(|m| m.to_lowercase()).collect();
+    if let Some(fp) = &opt.aps {
+        macs.extend(load_aps(fp)?.iter().map(|m| m.to_lowercase()));
+    }
+
+    match opt.action {
+        SnapshotAction::Capture => {
+            for mac in &macs {
+                let rpc = SysCtrl::cluster(&opt.cluster, mac)?;
+                match Snapshot::capture(&rpc, &sections).await {
+                    Ok(snap) => println!("{} {}", mac, snap.save(&opt.dir)?),
+                    Err(e) => warn!("{} capture failed, {}", mac, e),
+                }
+            }
+        }
+        SnapshotAction::Diff => {
+            if let Some(golden) = &opt.golden {
+                let mut g = Snapshot::load(golden)?;
+                g.conf.retain(|k, _| sections.contains(&k.as_str()));
+                for mac in &macs {
+                    let rpc = SysCtrl::cluster(&opt.cluster, mac)?;
+                    match Snapshot::capture(&rpc, &sections).await {
+                        Ok(snap) => print_diff(golden, mac, &diff(&g.conf, &snap.conf)),
+                        Err(e) => warn!("{} capture failed, {}", mac, e),
+                    }
+                }
+            } else if opt.files.len() == 2 {
+                let (a, b) = (
+                    Snapshot::load(&opt.files[0])?,
+                    Snapshot::load(&opt.files[1])?,
+                );
+                print_diff(&opt.files[0], &opt.files[1], &diff(&a.conf, &b.conf));
+            } else {
+                return Err(anyhow!(
+                    "diff needs two files, or --golden with --mac/--aps"
+                ));
+            }
+        }
+        SnapshotAction::Restore => {
+            let fp = opt
+                .files
+                .first()
+                .ok_or(anyhow!("restore needs a snapshot file"))?;
+            let snap = Snapshot::load(fp)?;
+            // 不填基站时恢复到快照自己的基站，填了就是克隆
+            if macs.is_empty() {
+                macs.push(snap.mac.clone());
+            }
+            // 克隆到别的基站时不写 net，免得地址冲突，除非明确指定 --section net
+            let net = opt.section.iter().any(|s| s == NET);
+            for mac in &macs {
+                let rpc = SysCtrl::cluster(&opt.cluster, mac)?;
+                let mut sections = sections.clone();
+                if !net && !same_mac(mac, &snap.mac) {
+                    info!("{} is not {}, skip {}", mac, snap.mac, NET);
+                    sections.retain(|s| *s != NET);
+                }
+                let failed = snap.restore(&rpc, &sections).await;
+                println!("{} restore from {}, failed {:?}", mac, fp, failed);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, same_mac};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_diff() {
+        let mut a = BTreeMap::new();
+        let mut b = BTreeMap::new();
+        a.insert("net".to_string(), json!({"dhcp": true, "dns": ["8.8.8.8"]}));
+        b.insert(
+            "net".to_string(),
+            json!({"dhcp": false, "dns": ["8.8.8.8"]}),
+        );
+        b.insert("timezone".to_string(), json!({"tz": "Asia/Shanghai"}));
+        let d = diff(&a, &b);
+        assert_eq!(d.len(), 2);
+        assert_eq!(
+            d[0],
+            (
+                "net.dhcp".to_string(),
+                Some(json!(true)),
+                Some(json!(false))
+            )
+        );
+        assert_eq!(d[1].0, "timezone.tz");
+        assert_eq!(d[1].1, None);
+        assert!(same_mac("3e:ad:54:e1:13:4e", "3EAD54E1134E"));
+        assert!(!same_mac("3e:ad:54:e1:13:4e", "3e:ad:54:e1:13:4f"));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::aplog;
+use crate::sysctrl::SysCtrl;
+use crate::upgrade::{image_version, query_version, send_upgrade, wait_version};
+use anyhow_ext::{anyhow, Context, Result};
+use chrono::Local;
+use log::{info, warn};
+use reqwest::Client;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::time::sleep;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct SoakOpt {
+    /// ew 基站接口地址
+    #[structopt(long, default_value = "http://172.16.120.59:9264")]
+    pub server: String,
+
+    /// usercode
+    #[structopt(long, default_value = "default")]
+    pub uc: String,
+
+    /// 基站 mac
+    #[structopt(long)]
+    pub mac: String,
+
+    /// 固件 a 的地址
+    #[structopt(long)]
+    pub image_a: String,
+
+    /// 固件 b 的地址
+    #[structopt(long)]
+    pub image_b: String,
+
+    /// ew 回调地址
+    #[structopt(long, default_value = "http://127.0.0.1:8080")]
+    pub back_url: String,
+
+    /// 轮数，一次升级或降级算一轮
+    #[structopt(long, default_value = "200")]
+    pub cycles: u32,
+
+    /// 单轮等版本的超时，单位为秒
+    #[structopt(long, default_value = "900")]
+    pub timeout: u64,
+
+    /// 查询版本的间隔，单位为秒
+    #[structopt(long, default_value = "15")]
+    pub poll: u64,
+
+    /// 版本到了之后等这么多秒再检查
+    #[structopt(long, default_value = "30")]
+    pub settle: u64,
+
+    /// 集群地址 https://host:9900，填了才检查 ping 和容器
+    #[structopt(long)]
+    pub cluster: Option<String>,
+
+    /// 要求在运行的容器，可以填多个
+    #[structopt(long)]
+    pub container: Vec<String>,
+
+    /// 连续失败这么多轮就停止，0 为不停
+    #[structopt(long, default_value = "0")]
+    pub max_fail: u32,
+
+    /// 每轮结果追加到这个文件
+    #[structopt(long, default_value = "soak_result.txt")]
+    pub out: String,
+
+    /// 失败时自动取日志，填 blob 的 org/store，需要 --cluster
+    #[structopt(long)]
+    pub collect: Option<String>,
+}
+
+/**
+ * 单轮结果
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoakResult {
+    Pass,
+    SendFailed,    // 升级请求失败
+    Timeout,       // 没等到目标版本
+    PingFailed,    // 版本对了但 ping 不通
+    ContainerDown, // 容器没在运行
+}
+
+#[derive(Debug, Clone)]
+pub struct CycleResult {
+    pub cycle: u32,
+    pub from: Option<String>,
+    pub target: String,
+    pub result: SoakResult,
+    pub secs: u64,
+    pub detail: String,
+}
+
+impl CycleResult {
+    pub fn line(&self) -> String {
+        format!(
+            "{} {:>4} {:<16} -> {:<16} {:<13} {:>5}s {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            self.cycle,
+            self.from.as_deref().unwrap_or("-"),
+            self.target,
+            format!("{:?}", self.result),
+            self.secs,
+            self.detail
+        )
+    }
+}
+
+/**
+ * 按结果累计
+ */
+#[derive(Debug, Default, Clone)]
+pub struct SoakStat {
+    pub pass: u32,
+    pub send_failed: u32,
+    pub timeout: u32,
+    pub ping_failed: u32,
+    pub container_down: u32,
+    pub total_secs: u64,
+}
+
+impl SoakStat {
+    pub fn add(&mut self, r: &CycleResult) {
+        match r.result {
+            SoakResult::Pass => self.pass += 1,
+            SoakResult::SendFailed => self.send_failed += 1,
+            SoakResult::Timeout => self.timeout += 1,
+            SoakResult::PingFailed => self.ping_failed += 1,
+            SoakResult::ContainerDown => self.container_down += 1,
+        }
+        self.total_secs += r.secs;
+    }
+
+    pub fn total(&self) -> u32 {
+        self.pass + self.send_failed + self.timeout + self.ping_failed + self.container_down
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "cycles {}, pass {}, send failed {}, timeout {}, ping failed {}, container down {}, avg {}s",
+            self.total(),
+            self.pass,
+            self.send_failed,
+            self.timeout,
+            self.ping_failed,
+            self.container_down,
+            self.total_secs / self.total().max(1) as u64
+        )
+    }
+}
+
+/**
+ * 升降级循环
+ */
+pub struct Soak {
+    opt: SoakOpt,
+    client: Client,
+    rpc: Option<SysCtrl>, // 填了 cluster 才有
+    versions: [String; 2],
+    pub stat: SoakStat,
+}
+
+impl Soak {
+    pub fn new(opt: SoakOpt) -> Result<Self> {
+        let versions = [
+            image_version(&opt.image_a).ok_or(anyhow!("can't get version from image_a"))?,
+            image_version(&opt.image_b).ok_or(anyhow!("can't get version from image_b"))?,
+        ];
+        if versions[0] == versions[1] {
+            return Err(anyhow!("image_a and image_b are both {}", versions[0]));
+        }
+        // 集群的设备路径用小写 mac，ew 接口仍用原样的 mac
+        let rpc = match &opt.cluster {
+            Some(cluster) => Some(SysCtrl::cluster(cluster, &opt.mac.to_lowercase())?),
+            None => None,
+        };
+        Ok(Self {
+            opt,
+            client: Client::new(),
+            rpc,
+            versions,
+            stat: SoakStat::default(),
+        })
+    }
+
+    // 检查 ping 和容器，返回失败的原因
+    async fn health(&self) -> Option<(SoakResult, String)> {
+        let rpc = self.rpc.as_ref()?;
+        if let Err(e) = rpc.ping().await {
+            return Some((SoakResult::PingFailed, e.to_string()));
+        }
+        if self.opt.container.is_empty() {
+            return None;
+        }
+        let status = match rpc.container_status().await {
+            Ok(v) => v,
+            Err(e) => return Some((SoakResult::ContainerDown, e.to_string())),
+        };
+        let down: Vec<&str> = self
+            .opt
+            .container
+            .iter()
+            .filter(|c| !status.iter().any(|s| &s.name == *c && s.running()))
+            .map(|c| c.as_str())
+            .collect();
+        (!down.is_empty()).then(|| (SoakResult::ContainerDown, down.join(",")))
+    }
+
+    /// 跑一轮，升到 target 之后检查
+    pub async fn run(&mut self, cycle: u32, from: Option<String>) -> CycleResult {
+        let start = Instant::now();
+        // 当前是 a 就升到 b，否则升到 a
+        let idx = if from.as_deref() == Some(self.versions[0].as_str()) {
+            1
+        } else {
+            0
+        };
+        let image = [&self.opt.image_a, &self.opt.image_b][idx];
+        let target = self.versions[idx].clone();
+        let mut ret = CycleResult {
+            cycle,
+            from,
+            target: target.clone(),
+            result: SoakResult::Pass,
+            secs: 0,
+            detail: String::new(),
+        };
+        info!("cycle {} {:?} -> {}", cycle, ret.from, target);
+
+        let o = &self.opt;
+        if let Err(e) =
+            send_upgrade(&self.client, &o.server, &o.uc, &o.mac, image, &o.back_url).await
+        {
+            ret.result = SoakResult::SendFailed;
+            ret.detail = e.to_string();
+        } else {
+            let waited = wait_version(
+                &self.client,
+                &o.server,
+                &o.uc,
+                &o.mac,
+                &target,
+                o.timeout,
+                o.poll,
+            );
+            match waited.await {
+                Ok(_) => {
+                    sleep(Duration::from_secs(o.settle)).await;
+                    if let Some((result, detail)) = self.health().await {
+                        ret.result = result;
+                        ret.detail = detail;
+                    }
+                }
+                Err(last) => {
+                    ret.result = SoakResult::Timeout;
+                    ret.detail = format!("last version {:?}", last);
+                }
+            }
+        }
+        ret.secs = start.elapsed().as_secs();
+        self.stat.add(&ret);
+        if ret.result != SoakResult::Pass {
+            self.collect_log().await;
+        }
+        ret
+    }
+
+    async fn collect_log(&self) {
+        let (Some(rpc), Some(store)) = (&self.rpc, &self.opt.collect) else {
+            return;
+        };
+        if let Err(e) =
+            aplog::collect(rpc, store, &[], aplog::LOG_DIR, aplog::UPLOAD_WAIT, false).await
+        {
+            warn!("collect {} log failed, {}", self.opt.mac, e);
+        }
+    }
+}
+
+/**
+ * soak 子命令
+ */
+pub async fn run(opt: &SoakOpt) -> Result<()> {
+    let mut soak = Soak::new(opt.clone())?;
+    let mut out = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&opt.out)
+        .with_context(|| format!("open {} failed", opt.out))?;
+    let mut streak = 0;
+    for cycle in 1..=opt.cycles {
+        let from = query_version(&soak.client, &opt.server, &opt.uc, &opt.mac)
+            .await
+            .unwrap_or(None);
+        let r = soak.run(cycle, from).await;
+        let line = r.line();
+        println!("{}", line);
+        writeln!(out, "{}", line)?;
+        if r.result == SoakResult::Pass {
+            streak = 0;
+        } else {
+            streak += 1;
+            warn!("cycle {} {:?}, {}", cycle, r.result, r.detail);
+        }
+        if opt.max_fail > 0 && streak >= opt.max_fail {
+            warn!("{} cycles failed in a row, stop", streak);
+            break;
+        }
+    }
+    let summary = soak.stat.summary();
+    println!("{}", summary);
+    writeln!(out, "{}", summary)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Soak, SoakOpt};
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_cluster_mac() {
+        let opt = SoakOpt::from_iter([
+            "soak",
+            "--mac",
+            "3E:AD:54:E1:13:4E",
+            "--image-a",
+            "http://host/image.7.1.29.tar",
+            "--image-b",
+            "http://host/image.7.1.30.tar",
+            "--cluster",
+            "https://10.11.173.231:9900",
+        ]);
+        let soak = Soak::new(opt).unwrap();
+        assert_eq!(soak.opt.mac, "3E:AD:54:E1:13:4E");
+        assert_eq!(soak.rpc.unwrap().name(), "3e:ad:54:e1:13:4e");
+    }
+}
@@ -0,0 +1,432 @@
+use anyhow_ext::{anyhow, Result};
+use log::debug;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// 基站本地 sysctrl 的端口，conf/cmd/container 等
+pub const PORT_SYSCTRL: u16 = 5002;
+
+/// 基站本地 wifi 的端口
+pub const PORT_WIFI: u16 = 5003;
+
+/// 基站本地 fastfs 的端口，https
+pub const PORT_FASTFS: u16 = 5004;
+
+const RPC_TIMEOUT: u64 = 30;
+
+/**
+ * 请求发到哪里：集群代理转发，或者直接连基站本地端口
+ */
+#[derive(Debug, Clone)]
+pub enum Target {
+    Cluster { base: String, mac: String }, // https://host:9900
+    Local { host: String },                // 127.0.0.1 或基站 ip
+}
+
+/**
+ * sysctrl 的通用返回，没有 code 的当成功
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reply<T = Value> {
+    #[serde(default)]
+    pub code: i64,
+    #[serde(default)]
+    pub msg: String,
+    pub data: Option<T>, // 缺省为 None，加 default 会要求 T: Default
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NameReq {
+    pub sid: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegistryAuth {
+    pub uid: String,
+    pub pwd: String,
+    pub registries: String,
+}
+
+/**
+ * container/run 和 container/update 的参数
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerSpec {
+    pub sid: String,
+    pub name: String,
+    pub image: String,
+    pub cpu: f64,
+    pub mem: u32,
+    pub disk: u32,
+    pub device: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<RegistryAuth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+/**
+ * container/status 里的一个容器
+ */
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ContainerInfo {
+    pub name: String,
+    pub image: String,
+    #[serde(alias = "state")]
+    pub status: String,
+}
+
+impl ContainerInfo {
+    /// 状态含 running 或 up 开头算在运行
+    pub fn running(&self) -> bool {
+        let s = self.status.to_lowercase();
+        s.contains("running") || s.starts_with("up")
+    }
+}
+
+/**
+ * wifi/config_adv 里的一个频段，字段名和基站一致
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct WifiBand {
+    pub switch: String,
+    pub password: String,
+    #[serde(rename = "SSID")]
+    pub ssid: String,
+    pub auth_mode: String,
+    pub channel: String,
+    pub auto_channel: String,
+    pub tx_power: String,
+    pub hidden: String,
+    pub band_width: String,
+    pub mode: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WifiConfig {
+    pub w24: WifiBand,
+    pub w5g: WifiBand,
+    pub white_enable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guest: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WifiClient {
+    pub ssid: String,
+    pub pwd: String,
+    pub enabled: bool,
+}
+
+/**
+ * hw/led，v 显示内容，c 颜色，t 秒数，r 是否闪烁
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct Led {
+    pub v: String,
+    pub c: String,
+    pub t: u32,
+    pub r: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DhcpRange {
+    pub netmask: String,
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LogUpload {
+    pub file: Vec<String>,
+    pub sid: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FastfsSock {
+    pub remote_addr: String,
+    pub remote_port: u16,
+}
+
+/**
+ * 基站 sysctrl 接口，替代 cmd.txt 里的 curl
+ */
+#[derive(Debug, Clone)]
+pub struct SysCtrl {
+    client: Client,
+    pub target: Target,
+}
+
+impl SysCtrl {
+    pub fn new(target: Target) -> Result<Self> {
+        // 集群和 fastfs 都是自签名证书，同 curl -k
+        let client = Client::builder()
+            .danger_accept_invalid_certs(true)
+            .timeout(Duration::from_secs(RPC_TIMEOUT))
+            .build()?;
+        Ok(Self { client, target })
+    }
+
+    /// 经集群转发，base 如 https://10.11.173.231:9900
+    pub fn cluster(base: &str, mac: &str) -> Result<Self> {
+        Self::new(Target::Cluster {
+            base: base.trim_end_matches('/').to_string(),
+            mac: mac.to_string(),
+        })
+    }
+
+    /// 直接连基站本地端口
+    pub fn local(host: &str) -> Result<Self> {
+        Self::new(Target::Local {
+            host: host.to_string(),
+        })
+    }
+
+    /// 基站 mac，本地连接时为 host
+    pub fn name(&self) -> &str {
+        match &self.target {
+            Target::Cluster { mac, .. } => mac,
+            Target::Local { host } => host,
+        }
+    }
+
+    /// path 如 conf/net_get，ping 在 cluster 下也走 sysctrl
+    pub fn url(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        match &self.target {
+            Target::Cluster { base, mac } => {
+                format!("{}/api/cluster/devices/{}/rpc/sysctrl/{}", base, mac, path)
+            }
+            Target::Local { host } => {
+                let (scheme, port) = if path.starts_with("wifi/") {
+                    ("http", PORT_WIFI)
+                } else if path.starts_with("fastfs/") {
+                    ("https", PORT_FASTFS)
+                } else {
+                    ("http", PORT_SYSCTRL)
+                };
+                format!("{}://{}:{}/sysctrl/{}", scheme, host, port, path)
+            }
+        }
+    }
+
+    /// 原样的 json 请求和返回
+    pub async fn raw(&self, path: &str, body: &Value) -> Result<Value> {
+        let url = self.url(path);
+        let resp = self.client.post(&url).json(body).send().await?;
+        let status = resp.status();
+        let text = resp.text().await?;
+        debug!("{} {} {}", url, status, text);
+        if !status.is_success() {
+            return Err(anyhow!("{} status {}, {}", url, status, text));
+        }
+        Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)))
+    }
+
+    /// 带类型的请求，code 不为 0 时返回错误
+    pub async fn call<B: Serialize, R: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<Reply<R>> {
+        let mut v = self.raw(path, &serde_json::to_value(body)?).await?;
+        // 没有 code/data 包装的返回整个当 data
+        if v.get("code").is_none() && v.get("data").is_none() {
+            v = json!({ "data": v });
+        }
+        let reply: Reply<R> = serde_json::from_value(v)?;
+        if reply.code != 0 {
+            return Err(anyhow!("{} code {}, {}", path, reply.code, reply.msg));
+        }
+        Ok(reply)
+    }
+
+    async fn get(&self, path: &str) -> Result<Value> {
+        Ok(self.call(path, &json!({})).await?.data.unwrap_or_default())
+    }
+
+    pub async fn ping(&self) -> Result<Value> {
+        self.get("ping").await
+    }
+
+    /// conf/<name>_get，vendor/desc/server/net/web/wifi/timezone/internal_dhcp/dhcp_options
+    pub async fn conf_get(&self, name: &str) -> Result<Value> {
+        self.get(&format!("conf/{}_get", name)).await
+    }
+
+    /// conf/<name>，写入配置
+    pub async fn conf_set(&self, name: &str, body: &Value) -> Result<Value> {
+        Ok(self
+            .call::<_, Value>(&format!("conf/{}", name), body)
+            .await?
+            .data
+            .unwrap_or_default())
+    }
+
+    pub async fn set_desc(&self, desc: &str) -> Result<Value> {
+        self.conf_set("desc", &json!({ "desc": desc })).await
+    }
+
+    pub async fn set_internal_dhcp(&self, range: &DhcpRange) -> Result<Value> {
+        self.conf_set("internal_dhcp", &serde_json::to_value(range)?)
+            .await
+    }
+
+    pub async fn ability_get(&self) -> Result<Value> {
+        self.get("cmd/ability_get").await
+    }
+
+    pub async fn usb_get(&self) -> Result<Value> {
+        self.get("cmd/usb_get").await
+    }
+
+    pub async fn set_usb(&self, status: bool) -> Result<Value> {
+        let r = self.call("cmd/usb", &json!({ "status": status })).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn container_get(&self) -> Result<Value> {
+        self.get("container/container_get").await
+    }
+
+    /// 所有容器的状态
+    pub async fn container_status(&self) -> Result<Vec<ContainerInfo>> {
+        let data = self.get("container/status").await?;
+        let items = [&data["list"], &data["containers"], &data]
+            .into_iter()
+            .find_map(|d| d.as_array())
+            .cloned()
+            .unwrap_or_default();
+        Ok(items
+            .into_iter()
+            .filter_map(|v| serde_json::from_value(v).ok())
+            .collect())
+    }
+
+    pub async fn images_status(&self) -> Result<Value> {
+        self.get("images/status").await
+    }
+
+    /// container/start|stop|restart|rm
+    pub async fn container_op(&self, op: &str, name: &str) -> Result<Value> {
+        let req = NameReq {
+            sid: String::new(),
+            name: name.to_string(),
+        };
+        let r = self.call(&format!("container/{}", op), &req).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn container_run(&self, spec: &ContainerSpec) -> Result<Value> {
+        let r = self.call("container/run", spec).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn container_update(&self, spec: &ContainerSpec) -> Result<Value> {
+        let r = self.call("container/update", spec).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn wifi_config_get(&self) -> Result<Value> {
+        self.get("wifi/config_get").await
+    }
+
+    pub async fn wifi_config_adv(&self, conf: &WifiConfig) -> Result<Value> {
+        let r = self.call("wifi/config_adv", conf).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn wifi_client_adv(&self, client: &WifiClient) -> Result<Value> {
+        let r = self.call("wifi/client_adv", client).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn online_dev_get(&self) -> Result<Value> {
+        self.get("wifi/online_dev_get").await
+    }
+
+    pub async fn wifi_reboot(&self) -> Result<Value> {
+        self.get("wifi/reboot").await
+    }
+
+    pub async fn led(&self, led: &Led) -> Result<Value> {
+        let r = self.call("hw/led", led).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    /// file 为空时列出日志，否则上传这些日志到 blob
+    pub async fn log_upload(&self, files: &[String], sid: &str) -> Result<Value> {
+        let req = LogUpload {
+            file: files.to_vec(),
+            sid: sid.to_string(),
+        };
+        let r = self.call("log/upload", &req).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn fastfs_sock_start(&self, sock: &FastfsSock) -> Result<Value> {
+        let r = self.call("fastfs/sock_start", sock).await?;
+        Ok(r.data.unwrap_or_default())
+    }
+
+    pub async fn fastfs_sock_status(&self) -> Result<Value> {
+        self.get("fastfs/sock_status").await
+    }
+
+    /// 下载集群 blob，path 如 <org>/<store>/aplog/<MAC>/messages
+    pub async fn blob(&self, path: &str) -> Result<Vec<u8>> {
+        let Target::Cluster { base, .. } = &self.target else {
+            return Err(anyhow!("blob needs cluster target"));
+        };
+        let url = format!("{}/api/blob/{}", base, path.trim_start_matches('/'));
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("{} status {}", url, resp.status()));
+        }
+        Ok(resp.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SysCtrl, WifiBand};
+
+    #[test]
+    fn test_url() {
+        let c = SysCtrl::cluster("https://10.11.173.231:9900/", "3e:ad:54:e1:13:4e").unwrap();
+        assert_eq!(
+            c.url("conf/net_get"),
+            "https://10.11.173.231:9900/api/cluster/devices/3e:ad:54:e1:13:4e/rpc/sysctrl/conf/net_get"
+        );
+        let l = SysCtrl::local("127.0.0.1").unwrap();
+        assert_eq!(
+            l.url("wifi/config_get"),
+            "http://127.0.0.1:5003/sysctrl/wifi/config_get"
+        );
+        assert_eq!(
+            l.url("fastfs/sock_status"),
+            "https://127.0.0.1:5004/sysctrl/fastfs/sock_status"
+        );
+        assert_eq!(
+            l.url("container/start"),
+            "http://127.0.0.1:5002/sysctrl/container/start"
+        );
+
+        let band = serde_json::to_value(WifiBand {
+            ssid: "apv2_test_joker".to_string(),
+            tx_power: "100".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(band["SSID"], "apv2_test_joker");
+        assert_eq!(band["TxPower"], "100");
+    }
+}
@@ -287,11 +287,11 @@ mod test {
 
     #[test]
     fn test_filter_lines() {
-        let ret = UartServer::filter_lines(&vec![], &"".to_string()).unwrap();
+        let ret = UartServer::filter_lines(&vec![], "").unwrap();
         assert_eq!(ret, None);
-        let ret = UartServer::filter_lines(&vec![], &"\n".to_string()).unwrap();
+        let ret = UartServer::filter_lines(&vec![], "\n").unwrap();
         assert_eq!(ret, None);
-        let ret = UartServer::filter_lines(&vec![], &"\r\nOpenWrt login:".to_string()).unwrap();
+        let ret = UartServer::filter_lines(&vec![], "\r\nOpenWrt login:").unwrap();
         assert_eq!(ret, Some("OpenWrt login:".to_string()));
     }
 }
@@ -0,0 +1,460 @@
+use crate::aplog;
+use crate::serve::{self, ServeOpt};
+use crate::sysctrl::SysCtrl;
+use anyhow_ext::{anyhow, Context, Result};
+use log::{info, warn};
+use regex::Regex;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+
+/// 基站升级的管理类型
+const UPGRADE_TYPE: u32 = 52;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct UpgradeOpt {
+    /// ew 基站接口地址
+    #[structopt(long, default_value = "http://172.16.120.59:9264")]
+    pub server: String,
+
+    /// usercode
+    #[structopt(long, default_value = "default")]
+    pub uc: String,
+
+    /// 基站 mac 列表文件，一行一个
+    #[structopt(long, default_value = "ap.txt")]
+    pub aps: String,
+
+    /// 升级的固件地址
+    #[structopt(long)]
+    pub image: String,
+
+    /// 目标版本，不填从固件文件名 image.<版本>.tar 取
+    #[structopt(long)]
+    pub version: Option<String>,
+
+    /// 失败时回退的固件地址，回退到升级前的版本
+    #[structopt(long)]
+    pub rollback: Option<String>,
+
+    /// ew 回调地址
+    #[structopt(long, default_value = "http://127.0.0.1:8080")]
+    pub back_url: String,
+
+    /// 每波升级的基站数
+    #[structopt(long, default_value = "10")]
+    pub wave: usize,
+
+    /// 同时升级的基站数
+    #[structopt(long, default_value = "3")]
+    pub concurrency: usize,
+
+    /// 失败重试次数
+    #[structopt(long, default_value = "1")]
+    pub retry: u32,
+
+    /// 单台升级超时，单位为秒
+    #[structopt(long, default_value = "900")]
+    pub timeout: u64,
+
+    /// 查询版本的间隔，单位为秒
+    #[structopt(long, default_value = "15")]
+    pub poll: u64,
+
+    /// 一波里有失败就不再升级后面的
+    #[structopt(long)]
+    pub halt_on_failure: bool,
+
+    /// 结果表写入这个文件
+    #[structopt(long, default_value = "upgrade_result.txt")]
+    pub out: String,
+
+    /// 同时在这个地址提供固件下载，--image 填 http://<本机ip>:<端口>/blob/<文件名>
+    #[structopt(long)]
+    pub serve: Option<String>,
+
+    /// 提供下载的固件目录
+    #[structopt(long, default_value = "images")]
+    pub serve_dir: String,
+
+    /// 集群地址 https://host:9900，取日志用
+    #[structopt(long)]
+    pub cluster: Option<String>,
+
+    /// 失败时自动取日志，填 blob 的 org/store，需要 --cluster
+    #[structopt(long)]
+    pub collect: Option<String>,
+}
+
+/**
+ * 单台基站的升级结果
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpgradeResult {
+    Success,
+    Skipped,    // 已经是目标版本
+    Failed,     // 重试后仍失败
+    RolledBack, // 失败后已回退
+    Halted,     // 前面的波次失败，没有升级
+}
+
+#[derive(Debug, Clone)]
+pub struct ApResult {
+    pub mac: String,
+    pub from: Option<String>,
+    pub to: Option<String>, // 最后查到的版本
+    pub result: UpgradeResult,
+    pub attempts: u32,
+    pub secs: u64,
+    pub error: String,
+}
+
+/**
+ * 发送一次升级请求
+ */
+pub async fn send_upgrade(
+    client: &Client,
+    server: &str,
+    uc: &str,
+    mac: &str,
+    image: &str,
+    back_url: &str,
+) -> Result<()> {
+    let data = vec![json!({
+        "apMac": mac,
+        "back_url": back_url,
+        "data": {
+            "server_url": image
+        },
+        "type": UPGRADE_TYPE
+    })];
+    let url = format!("{}/api3/{}/aps/management", server, uc);
+    let res = client
+        .put(&url)
+        .timeout(Duration::from_secs(10))
+        .json(&data)
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(anyhow!("upgrade {} status {}", mac, res.status()));
+    }
+    Ok(())
+}
+
+/**
+ * 查询基站当前版本，基站离线时返回 None
+ */
+pub async fn query_version(
+    client: &Client,
+    server: &str,
+    uc: &str,
+    mac: &str,
+) -> Result<Option<String>> {
+    let url = format!("{}/api3/{}/aps/{}", server, uc, mac);
+    let v: Value = client
+        .get(&url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let data = &v["data"];
+    if data["online"].as_bool() == Some(false) {
+        return Ok(None);
+    }
+    Ok(["version", "firmware_version", "sw_version"]
+        .iter()
+        .find_map(|k| data[*k].as_str())
+        .map(|s| s.to_string()))
+}
+
+/**
+ * 从 image.7.1.29_rc6.tar 取出 7.1.29_rc6
+ */
+pub fn image_version(image: &str) -> Option<String> {
+    let re = Regex::new(r"image\.(.+?)\.tar").unwrap();
+    re.captures(image).map(|c| c[1].to_string())
+}
+
+/**
+ * 读取基站列表，跳过空行和 # 开头的行
+ */
+pub fn load_aps(fp: &str) -> Result<Vec<String>> {
+    let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+    Ok(text
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_uppercase())
+        .collect())
+}
+
+/**
+ * 等基站上报目标版本，超时返回最后查到的版本
+ */
+pub async fn wait_version(
+    client: &Client,
+    server: &str,
+    uc: &str,
+    mac: &str,
+    target: &str,
+    timeout: u64,
+    poll: u64,
+) -> std::result::Result<String, Option<String>> {
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let mut last = None;
+    while Instant::now() < deadline {
+        sleep(Duration::from_secs(poll)).await;
+        match query_version(client, server, uc, mac).await {
+            Ok(Some(v)) if v == target => return Ok(v),
+            Ok(v) => last = v.or(last),
+            Err(e) => warn!("query {} version failed, {}", mac, e),
+        }
+    }
+    Err(last)
+}
+
+async fn upgrade_one(
+    client: Client,
+    opt: Arc<UpgradeOpt>,
+    target: String,
+    mac: String,
+) -> ApResult {
+    let start = Instant::now();
+    let from = query_version(&client, &opt.server, &opt.uc, &mac)
+        .await
+        .unwrap_or(None);
+    let mut ret = ApResult {
+        mac: mac.clone(),
+        from: from.clone(),
+        to: from.clone(),
+        result: UpgradeResult::Failed,
+        attempts: 0,
+        secs: 0,
+        error: String::new(),
+    };
+    if from.as_deref() == Some(target.as_str()) {
+        ret.result = UpgradeResult::Skipped;
+        return ret;
+    }
+
+    while ret.attempts <= opt.retry {
+        ret.attempts += 1;
+        info!(
+            "upgrade {} {:?} -> {}, attempt {}",
+            mac, from, target, ret.attempts
+        );
+        if let Err(e) = send_upgrade(
+            &client,
+            &opt.server,
+            &opt.uc,
+            &mac,
+            &opt.image,
+            &opt.back_url,
+        )
+        .await
+        {
+            ret.error = e.to_string();
+            // ew 短暂不可用时不要一下把重试用完，按次数退避
+            if ret.attempts <= opt.retry {
+                let wait = opt.poll * ret.attempts as u64;
+                warn!("send upgrade {} failed, retry in {}s, {}", mac, wait, e);
+                sleep(Duration::from_secs(wait)).await;
+            }
+            continue;
+        }
+        let waited = wait_version(
+            &client,
+            &opt.server,
+            &opt.uc,
+            &mac,
+            &target,
+            opt.timeout,
+            opt.poll,
+        );
+        match waited.await {
+            Ok(v) => {
+                ret.to = Some(v);
+                ret.result = UpgradeResult::Success;
+                ret.error.clear();
+                break;
+            }
+            Err(last) => {
+                ret.to = last;
+                ret.error = format!("timeout after {}s", opt.timeout);
+            }
+        }
+    }
+
+    if ret.result == UpgradeResult::Failed {
+        if let (Some(image), Some(from)) = (&opt.rollback, &from) {
+            warn!("upgrade {} failed, roll back to {}", mac, from);
+            let back = match send_upgrade(&client, &opt.server, &opt.uc, &mac, image, &opt.back_url)
+                .await
+            {
+                Ok(()) => wait_version(
+                    &client,
+                    &opt.server,
+                    &opt.uc,
+                    &mac,
+                    from,
+                    opt.timeout,
+                    opt.poll,
+                )
+                .await
+                .ok(),
+                Err(e) => {
+                    warn!("roll back {} failed, {}", mac, e);
+                    None
+                }
+            };
+            if let Some(v) = back {
+                ret.to = Some(v);
+                ret.result = UpgradeResult::RolledBack;
+            }
+        }
+    }
+    ret.secs = start.elapsed().as_secs();
+    info!("upgrade {} {:?}, {}", mac, ret.result, ret.error);
+    ret
+}
+
+// 一波结束后取失败基站的日志，取不到只记录
+async fn collect_logs(opt: &UpgradeOpt, results: &[ApResult]) {
+    let (Some(cluster), Some(store)) = (&opt.cluster, &opt.collect) else {
+        return;
+    };
+    let failed = results
+        .iter()
+        .filter(|r| r.result == UpgradeResult::Failed || r.result == UpgradeResult::RolledBack);
+    for r in failed {
+        // 集群的设备路径用小写 mac
+        let ret = match SysCtrl::cluster(cluster, &r.mac.to_lowercase()) {
+            Ok(rpc) => {
+                aplog::collect(&rpc, store, &[], aplog::LOG_DIR, aplog::UPLOAD_WAIT, false).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            warn!("collect {} log failed, {}", r.mac, e);
+        }
+    }
+}
+
+/**
+ * 分波升级，每波内按 concurrency 并发
+ */
+pub async fn upgrade(opt: &UpgradeOpt, aps: &[String]) -> Result<Vec<ApResult>> {
+    let target = match &opt.version {
+        Some(v) => v.clone(),
+        None => image_version(&opt.image)
+            .ok_or(anyhow!("can't get version from image, use --version"))?,
+    };
+    let opt = Arc::new(opt.clone());
+    let client = Client::new();
+    let sem = Arc::new(Semaphore::new(opt.concurrency.max(1)));
+    let mut results = Vec::new();
+
+    for (n, wave) in aps.chunks(opt.wave.max(1)).enumerate() {
+        info!("wave {} start, {} ap, target {}", n + 1, wave.len(), target);
+        let mut set = JoinSet::new();
+        for mac in wave {
+            let (client, opt, target, mac, sem) = (
+                client.clone(),
+                opt.clone(),
+                target.clone(),
+                mac.clone(),
+                sem.clone(),
+            );
+            set.spawn(async move {
+                let _permit = sem.acquire_owned().await;
+                upgrade_one(client, opt, target, mac).await
+            });
+        }
+        let mut wave_ret = Vec::new();
+        while let Some(r) = set.join_next().await {
+            match r {
+                Ok(r) => wave_ret.push(r),
+                Err(e) => warn!("upgrade task failed, {}", e),
+            }
+        }
+        let failed = wave_ret
+            .iter()
+            .filter(|r| r.result != UpgradeResult::Success && r.result != UpgradeResult::Skipped)
+            .count();
+        info!("wave {} finish, failed {}", n + 1, failed);
+        if failed > 0 {
+            collect_logs(&opt, &wave_ret).await;
+        }
+        results.extend(wave_ret);
+        if failed > 0 && opt.halt_on_failure {
+            // 后面没升级的也列进结果表，总数和基站列表对得上
+            let rest = &aps[((n + 1) * opt.wave.max(1)).min(aps.len())..];
+            warn!("halt on failure, skip {} ap", rest.len());
+            results.extend(rest.iter().map(|mac| ApResult {
+                mac: mac.clone(),
+                from: None,
+                to: None,
+                result: UpgradeResult::Halted,
+                attempts: 0,
+                secs: 0,
+                error: "not run, halted on failure".to_string(),
+            }));
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/**
+ * 结果表
+ */
+pub fn render(results: &[ApResult]) -> String {
+    let mut out = format!(
+        "{:<18} {:<16} {:<16} {:<10} {:>8} {:>6} {}\n",
+        "mac", "from", "to", "result", "attempts", "second", "error"
+    );
+    for r in results {
+        out.push_str(&format!(
+            "{:<18} {:<16} {:<16} {:<10} {:>8} {:>6} {}\n",
+            r.mac,
+            r.from.as_deref().unwrap_or("-"),
+            r.to.as_deref().unwrap_or("-"),
+            format!("{:?}", r.result),
+            r.attempts,
+            r.secs,
+            r.error
+        ));
+    }
+    out
+}
+
+/**
+ * upgrade 子命令
+ */
+pub async fn run(opt: &UpgradeOpt) -> Result<()> {
+    let aps = load_aps(&opt.aps)?;
+    if let Some(addr) = &opt.serve {
+        serve::start(&ServeOpt {
+            addr: addr.clone(),
+            dir: opt.serve_dir.clone(),
+        })
+        .await?;
+    }
+    let results = upgrade(opt, &aps).await?;
+    let table = render(&results);
+    print!("{}", table);
+    fs::write(&opt.out, table).with_context(|| format!("write {} failed", opt.out))?;
+    let ok = results
+        .iter()
+        .filter(|r| r.result == UpgradeResult::Success || r.result == UpgradeResult::Skipped)
+        .count();
+    println!("{}/{} ap on target, write to {}", ok, aps.len(), opt.out);
+    Ok(())
+}
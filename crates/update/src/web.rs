@@ -1,10 +1,14 @@
 
-use anyhow_ext::{Ok, Result};
+use anyhow_ext::{anyhow, Context, Ok, Result};
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_with::{serde_as, DisplayFromStr};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use structopt::StructOpt;
 use regex::Regex;
 
 /// 升级基站
@@ -45,7 +49,7 @@ pub async fn upgrade_ap() -> Result<()> {
     Ok(())
 }
 
-fn check_essid(input: &str) -> bool {
+pub fn check_essid(input: &str) -> bool {
     // 创建一个正则表达式来匹配 ESSID 的值
     let re = Regex::new(r#"ESSID:"(.*?)""#).unwrap();
 
@@ -61,8 +65,38 @@ fn check_essid(input: &str) -> bool {
     false
 }
 
+// cgi 里没填的地址是空字符串
+mod empty_none {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(v: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+        match v {
+            Some(v) => s.collect_str(v),
+            None => s.serialize_str(""),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(d)?;
+        if s.is_empty() {
+            return Ok(None);
+        }
+        s.parse().map(Some).map_err(de::Error::custom)
+    }
+}
+
+/**
+ * 基站 web/cgi 的网络和 ew 连接参数，cgi 用扁平的字符串键值对
+ */
 #[serde_as]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub struct CgiParm {
     pub ssl: String,
@@ -74,6 +108,20 @@ pub struct CgiParm {
     pub net_dhcp: bool,
     #[serde_as(as = "DisplayFromStr")]
     pub net_iptype: String,
+    #[serde(default, with = "empty_none")]
+    pub net_ipaddr: Option<Ipv4Addr>,
+    #[serde(default, with = "empty_none")]
+    pub net_ipaddr6: Option<Ipv6Addr>,
+    #[serde(default, with = "empty_none")]
+    pub net_netmask: Option<Ipv4Addr>,
+    #[serde(default, with = "empty_none")]
+    pub net_router: Option<Ipv4Addr>,
+    #[serde(default, with = "empty_none")]
+    pub net_router6: Option<Ipv6Addr>,
+    #[serde(default, with = "empty_none")]
+    pub net_dns1: Option<IpAddr>,
+    #[serde(default, with = "empty_none")]
+    pub net_dns2: Option<IpAddr>,
 
     #[serde_as(as = "DisplayFromStr")]
     pub ew_auto: bool,
@@ -88,42 +136,232 @@ pub struct CgiParm {
     pub ew_wss: bool,
 }
 
-fn into2data() -> Result<()> {
-    let mut data: HashMap<String, String> = HashMap::new();
-    data.insert("ssl".to_string(), "enabled".to_string());
-    data.insert("wss".to_string(), "true".to_string());
-    data.insert("port".to_string(), "8080".to_string());
-    data.insert("net_dhcp".to_string(), "true".to_string());
-    data.insert("net_iptype".to_string(), "static".to_string());
-    data.insert("net_ipaddr".to_string(), "192.168.1.100".to_string());
-    data.insert("net_ipaddr6".to_string(), "::1".to_string());
-    data.insert("net_netmask".to_string(), "255.255.255.0".to_string());
-    data.insert("net_router".to_string(), "192.168.1.1".to_string());
-    data.insert("net_router6".to_string(), "::1".to_string());
-    data.insert("net_dns1".to_string(), "8.8.8.8".to_string());
-    data.insert("net_dns2".to_string(), "8.8.4.4".to_string());
-    data.insert("ew_auto".to_string(), "false".to_string());
-    data.insert("ew_ipaddr".to_string(), "example.com".to_string());
-    data.insert("ew_port".to_string(), "443".to_string());
-    data.insert("ew_ssl".to_string(), "true".to_string());
-    data.insert("ew_ssl_mutual_auth".to_string(), "false".to_string());
-    data.insert("ew_wss".to_string(), "true".to_string());
-
-    // 将 HashMap<String, String> 转换为 serde_json::Value
-    let json_value = serde_json::to_value(&data)?;
-
-    // 反序列化为 CgiParm
-    let cgi_parm: CgiParm = serde_json::from_value(json_value)?;
-
-    println!("{:#?}", cgi_parm);
+impl CgiParm {
+    /// 从 cgi 的键值对解析，多余的键忽略
+    pub fn from_map(map: &BTreeMap<String, String>) -> Result<Self> {
+        let parm: CgiParm = serde_json::from_value(serde_json::to_value(map)?)?;
+        Ok(parm)
+    }
 
-    Ok(())
+    /// 转成 cgi 的键值对，没填的地址为空字符串
+    pub fn to_map(&self) -> Result<BTreeMap<String, String>> {
+        let map: BTreeMap<String, String> = serde_json::from_value(serde_json::to_value(self)?)?;
+        Ok(map)
+    }
+
+    /// 改一个字段，按 cgi 的键名和字符串值
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let mut map = self.to_map()?;
+        if !map.contains_key(key) {
+            return Err(anyhow!("unknown key {}", key));
+        }
+        map.insert(key.to_string(), value.to_string());
+        *self = Self::from_map(&map).with_context(|| format!("bad value {}={}", key, value))?;
+        Ok(())
+    }
+
+    /// 检查参数之间的约束，返回所有问题
+    pub fn check(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.port == 0 {
+            issues.push("port is 0".to_string());
+        }
+        if !self.net_dhcp {
+            for (k, v) in [
+                ("net_ipaddr", self.net_ipaddr),
+                ("net_netmask", self.net_netmask),
+                ("net_router", self.net_router),
+            ] {
+                if v.is_none() {
+                    issues.push(format!("{} is required without dhcp", k));
+                }
+            }
+            if let Some(mask) = self.net_netmask {
+                let m = u32::from(mask);
+                if m.leading_ones() + m.trailing_zeros() != 32 {
+                    issues.push(format!("net_netmask {} is not contiguous", mask));
+                }
+            }
+            if let (Some(ip), Some(mask), Some(router)) =
+                (self.net_ipaddr, self.net_netmask, self.net_router)
+            {
+                let m = u32::from(mask);
+                if u32::from(ip) & m != u32::from(router) & m {
+                    issues.push(format!("net_router {} not in {}/{}", router, ip, mask));
+                }
+            }
+        }
+        if !self.ew_auto {
+            if self.ew_ipaddr.is_empty() {
+                issues.push("ew_ipaddr is required without ew_auto".to_string());
+            }
+            if self.ew_port == 0 {
+                issues.push("ew_port is 0".to_string());
+            }
+        }
+        if self.ew_ssl_mutual_auth && !self.ew_ssl {
+            issues.push("ew_ssl_mutual_auth needs ew_ssl".to_string());
+        }
+        issues
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let issues = self.check();
+        if !issues.is_empty() {
+            return Err(anyhow!("invalid cgi parm: {}", issues.join("; ")));
+        }
+        Ok(())
+    }
 }
 
-fn check() {
-    let itesm = vec!["a", "b", "c"];
-    for (idx, item) in itesm.iter().enumerate() {
-        println!("{}={}", idx + 1, item);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgiAction {
+    Get,   // 读取并检查
+    Apply, // 修改后写回
+}
+
+impl FromStr for CgiAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "get" => std::result::Result::Ok(Self::Get),
+            "apply" => std::result::Result::Ok(Self::Apply),
+            _ => Err(format!("unknown action {}, use get/apply", s)),
+        }
     }
 }
 
+#[derive(StructOpt, Debug, Clone)]
+pub struct CgiOpt {
+    /// get/apply
+    pub action: CgiAction,
+
+    /// 基站的 cgi 地址，如 http://192.168.1.100/cgi-bin/parm
+    #[structopt(long)]
+    pub url: String,
+
+    /// 要改的参数 key=value，可以填多个
+    #[structopt(long)]
+    pub set: Vec<String>,
+
+    /// 从这个文件读参数，不从基站读
+    #[structopt(long)]
+    pub file: Option<String>,
+
+    /// get 时把参数写入这个文件
+    #[structopt(long)]
+    pub out: Option<String>,
+
+    /// 只显示修改，不写回
+    #[structopt(long)]
+    pub dry_run: bool,
+}
+
+// cgi 返回的值不一定都是字符串
+async fn fetch_parm(client: &Client, url: &str) -> Result<CgiParm> {
+    let v: serde_json::Value = client.get(url).send().await?.json().await?;
+    let obj = v.as_object().ok_or(anyhow!("{} returns {}", url, v))?;
+    let map: BTreeMap<String, String> = obj
+        .iter()
+        .map(|(k, v)| match v.as_str() {
+            Some(s) => (k.clone(), s.to_string()),
+            None => (k.clone(), v.to_string()),
+        })
+        .collect();
+    CgiParm::from_map(&map)
+}
+
+/**
+ * cgi 子命令
+ */
+pub async fn cgi(opt: &CgiOpt) -> Result<()> {
+    let client = Client::new();
+    let old = match &opt.file {
+        Some(fp) => {
+            let text = fs::read_to_string(fp).with_context(|| format!("read {} failed", fp))?;
+            CgiParm::from_map(&serde_json::from_str(&text)?)?
+        }
+        None => fetch_parm(&client, &opt.url).await?,
+    };
+    let mut parm = old.clone();
+    for kv in &opt.set {
+        let (k, v) = kv
+            .split_once('=')
+            .ok_or(anyhow!("bad --set {}, use key=value", kv))?;
+        parm.set(k.trim(), v.trim())?;
+    }
+    let map = parm.to_map()?;
+
+    match opt.action {
+        CgiAction::Get => {
+            for (k, v) in &map {
+                println!("{}={}", k, v);
+            }
+            for issue in parm.check() {
+                println!("warning: {}", issue);
+            }
+            if let Some(fp) = &opt.out {
+                fs::write(fp, serde_json::to_string_pretty(&map)?)
+                    .with_context(|| format!("write {} failed", fp))?;
+            }
+        }
+        CgiAction::Apply => {
+            parm.validate()?;
+            let before = old.to_map()?;
+            for (k, v) in &map {
+                let was = before.get(k).map_or("-", |s| s.as_str());
+                if was != v.as_str() {
+                    println!("{}: {} -> {}", k, was, v);
+                }
+            }
+            if opt.dry_run {
+                return Ok(());
+            }
+            let res = client.post(&opt.url).form(&map).send().await?;
+            println!("apply status {}, {}", res.status(), res.text().await?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CgiParm;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_cgi_parm() {
+        let mut data = BTreeMap::new();
+        for (k, v) in [
+            ("ssl", "enabled"),
+            ("wss", "true"),
+            ("port", "8080"),
+            ("net_dhcp", "false"),
+            ("net_iptype", "static"),
+            ("net_ipaddr", "192.168.1.100"),
+            ("net_ipaddr6", "::1"),
+            ("net_netmask", "255.255.255.0"),
+            ("net_router", "192.168.1.1"),
+            ("net_router6", ""),
+            ("net_dns1", "8.8.8.8"),
+            ("net_dns2", "8.8.4.4"),
+            ("ew_auto", "false"),
+            ("ew_ipaddr", "example.com"),
+            ("ew_port", "443"),
+            ("ew_ssl", "true"),
+            ("ew_ssl_mutual_auth", "false"),
+            ("ew_wss", "true"),
+        ] {
+            data.insert(k.to_string(), v.to_string());
+        }
+        let mut parm = CgiParm::from_map(&data).unwrap();
+        assert_eq!(parm.net_router6, None);
+        assert!(parm.check().is_empty());
+        assert_eq!(parm.to_map().unwrap(), data);
+
+        parm.set("net_router", "10.0.0.1").unwrap();
+        assert_eq!(parm.check().len(), 1);
+        assert!(parm.set("net_netmask", "bad").is_err());
+    }
+}
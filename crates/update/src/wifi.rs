@@ -0,0 +1,295 @@
+use crate::sysctrl::{SysCtrl, WifiBand, WifiConfig};
+use crate::web::check_essid;
+use anyhow_ext::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::time::sleep;
+
+/// 推配置后先等这么多秒再查，基站会先断开
+const REACH_DELAY: u64 = 5;
+
+/// 查询基站是否可达的间隔，单位为秒
+const REACH_POLL: u64 = 2;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct WifiOpt {
+    /// 集群地址
+    #[structopt(long, default_value = "https://10.11.173.231:9900")]
+    pub cluster: String,
+
+    /// 基站 mac
+    #[structopt(long)]
+    pub mac: String,
+
+    /// wifi 配置文件，yaml 或 json
+    #[structopt(long, default_value = "wifi_profiles.yaml")]
+    pub profiles: String,
+
+    /// 轮数，每轮把所有配置推一遍
+    #[structopt(long, default_value = "100")]
+    pub rounds: u32,
+
+    /// 每个配置生效后停留的秒数
+    #[structopt(long, default_value = "100")]
+    pub interval: u64,
+
+    /// 等基站重新可达的超时，单位为秒
+    #[structopt(long, default_value = "300")]
+    pub timeout: u64,
+
+    /// 基站 ssh 地址 ip:port，填了用 iwconfig 检查 ESSID
+    #[structopt(long)]
+    pub ssh: Option<String>,
+
+    #[structopt(long, default_value = "root")]
+    pub ssh_user: String,
+
+    #[structopt(long, default_value = "")]
+    pub ssh_password: String,
+
+    /// 结果写入这个文件
+    #[structopt(long, default_value = "wifi_report.txt")]
+    pub out: String,
+}
+
+/**
+ * 一组 2.4G/5G 配置
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(flatten)]
+    pub conf: WifiConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileFile {
+    pub profiles: Vec<Profile>,
+}
+
+/**
+ * 每个配置的累计结果
+ */
+#[derive(Debug, Default, Clone)]
+pub struct ProfileStat {
+    pub pushed: u32,
+    pub push_failed: u32,
+    pub unreachable: u32,
+    pub mismatch: u32,
+    pub online_failed: u32,
+    pub essid_failed: u32,
+    pub reach_secs: u64, // 重新可达的累计时间
+    pub reach_max: u64,
+}
+
+impl ProfileStat {
+    pub fn failed(&self) -> u32 {
+        self.push_failed + self.unreachable + self.mismatch + self.online_failed + self.essid_failed
+    }
+}
+
+// 比较一个频段，密码不一定返回，不比较；自动信道时不比较信道
+fn band_diff(tag: &str, want: &WifiBand, got: &WifiBand) -> Vec<String> {
+    let mut fields = vec![
+        ("Switch", &want.switch, &got.switch),
+        ("SSID", &want.ssid, &got.ssid),
+        ("AuthMode", &want.auth_mode, &got.auth_mode),
+        ("TxPower", &want.tx_power, &got.tx_power),
+        ("BandWidth", &want.band_width, &got.band_width),
+    ];
+    if want.auto_channel != "1" {
+        fields.push(("Channel", &want.channel, &got.channel));
+    }
+    fields
+        .into_iter()
+        .filter(|(_, w, g)| !w.is_empty() && w != g)
+        .map(|(k, w, g)| format!("{}.{} {} != {}", tag, k, g, w))
+        .collect()
+}
+
+// ssh-rs 是阻塞的，放到 blocking 线程里跑
+async fn ssh_exec(addr: &str, user: &str, password: &str, cmd: &str) -> Result<String> {
+    let (addr, user, password, cmd) = (
+        addr.to_string(),
+        user.to_string(),
+        password.to_string(),
+        cmd.to_string(),
+    );
+    tokio::task::spawn_blocking(move || -> Result<String> {
+        let mut session = ssh::create_session()
+            .username(&user)
+            .password(&password)
+            .timeout(Some(Duration::from_secs(10)))
+            .connect(&addr)
+            .map_err(|e| anyhow!("ssh {} failed, {}", addr, e))?
+            .run_local();
+        let exec = session
+            .open_exec()
+            .map_err(|e| anyhow!("ssh exec failed, {}", e))?;
+        let out = exec
+            .send_command(&cmd)
+            .map_err(|e| anyhow!("ssh {} failed, {}", cmd, e))?;
+        session.close();
+        Ok(String::from_utf8_lossy(&out).to_string())
+    })
+    .await?
+}
+
+pub struct WifiStress {
+    opt: WifiOpt,
+    rpc: SysCtrl,
+    pub stat: BTreeMap<String, ProfileStat>,
+}
+
+impl WifiStress {
+    pub fn new(opt: WifiOpt) -> Result<Self> {
+        // 集群的设备路径用小写 mac
+        let rpc = SysCtrl::cluster(&opt.cluster, &opt.mac.to_lowercase())?;
+        Ok(Self {
+            opt,
+            rpc,
+            stat: BTreeMap::new(),
+        })
+    }
+
+    // 等 ping 通，返回用时
+    async fn wait_reachable(&self) -> Option<u64> {
+        let start = Instant::now();
+        sleep(Duration::from_secs(REACH_DELAY)).await;
+        while start.elapsed().as_secs() < self.opt.timeout {
+            if self.rpc.ping().await.is_ok() {
+                return Some(start.elapsed().as_secs());
+            }
+            sleep(Duration::from_secs(REACH_POLL)).await;
+        }
+        None
+    }
+
+    /// 推一个配置并检查，失败的原因写日志
+    pub async fn apply(&mut self, p: &Profile) {
+        let mut st = self.stat.remove(&p.name).unwrap_or_default();
+        st.pushed += 1;
+        info!("push wifi profile {}", p.name);
+        if let Err(e) = self.rpc.wifi_config_adv(&p.conf).await {
+            warn!("{} config_adv failed, {}", p.name, e);
+            st.push_failed += 1;
+            self.stat.insert(p.name.clone(), st);
+            return;
+        }
+
+        match self.wait_reachable().await {
+            Some(secs) => {
+                info!("{} reachable after {}s", p.name, secs);
+                st.reach_secs += secs;
+                st.reach_max = st.reach_max.max(secs);
+            }
+            None => {
+                warn!("{} unreachable after {}s", p.name, self.opt.timeout);
+                st.unreachable += 1;
+                self.stat.insert(p.name.clone(), st);
+                return;
+            }
+        }
+
+        let diff = match self.rpc.wifi_config_get().await {
+            Ok(v) => match serde_json::from_value::<WifiConfig>(v) {
+                Ok(got) => {
+                    let mut d = band_diff("w24", &p.conf.w24, &got.w24);
+                    d.extend(band_diff("w5g", &p.conf.w5g, &got.w5g));
+                    d
+                }
+                Err(e) => vec![format!("parse config_get failed, {}", e)],
+            },
+            Err(e) => vec![format!("config_get failed, {}", e)],
+        };
+        if !diff.is_empty() {
+            warn!("{} mismatch, {}", p.name, diff.join("; "));
+            st.mismatch += 1;
+        }
+
+        if let Err(e) = self.rpc.online_dev_get().await {
+            warn!("{} online_dev_get failed, {}", p.name, e);
+            st.online_failed += 1;
+        }
+
+        if let Some(addr) = &self.opt.ssh {
+            let o = &self.opt;
+            match ssh_exec(addr, &o.ssh_user, &o.ssh_password, "iwconfig 2>/dev/null").await {
+                Ok(out) if check_essid(&out) => {}
+                Ok(_) => {
+                    warn!("{} iwconfig has no ESSID", p.name);
+                    st.essid_failed += 1;
+                }
+                Err(e) => {
+                    warn!("{} {}", p.name, e);
+                    st.essid_failed += 1;
+                }
+            }
+        }
+        self.stat.insert(p.name.clone(), st);
+    }
+}
+
+/**
+ * 每个配置一行
+ */
+pub fn render(stat: &BTreeMap<String, ProfileStat>) -> String {
+    let mut out = format!(
+        "{:<20} {:>6} {:>6} {:>6} {:>8} {:>8} {:>6} {:>6} {:>8} {:>8}\n",
+        "profile",
+        "pushed",
+        "failed",
+        "push",
+        "unreach",
+        "mismatch",
+        "online",
+        "essid",
+        "reach_avg",
+        "reach_max"
+    );
+    for (name, s) in stat {
+        out.push_str(&format!(
+            "{:<20} {:>6} {:>6} {:>6} {:>8} {:>8} {:>6} {:>6} {:>8} {:>8}\n",
+            name,
+            s.pushed,
+            s.failed(),
+            s.push_failed,
+            s.unreachable,
+            s.mismatch,
+            s.online_failed,
+            s.essid_failed,
+            s.reach_secs / (s.pushed - s.push_failed - s.unreachable).max(1) as u64,
+            s.reach_max
+        ));
+    }
+    out
+}
+
+/**
+ * wifi 子命令，替代 wifi_yace.sh
+ */
+pub async fn run(opt: &WifiOpt) -> Result<()> {
+    let text = fs::read_to_string(&opt.profiles)
+        .with_context(|| format!("read {} failed", opt.profiles))?;
+    let file: ProfileFile =
+        serde_yaml::from_str(&text).with_context(|| format!("parse {} failed", opt.profiles))?;
+    if file.profiles.is_empty() {
+        return Err(anyhow!("no profile in {}", opt.profiles));
+    }
+    let mut stress = WifiStress::new(opt.clone())?;
+    for round in 1..=opt.rounds {
+        info!("wifi round {}/{}", round, opt.rounds);
+        for p in &file.profiles {
+            stress.apply(p).await;
+            sleep(Duration::from_secs(opt.interval)).await;
+        }
+    }
+    let table = render(&stress.stat);
+    print!("{}", table);
+    fs::write(&opt.out, table).with_context(|| format!("write {} failed", opt.out))?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use crate::eslog::{EslAction, EslLog};
+use crate::report::Report;
+use crate::round::{Round, RoundEnd};
+use crate::status::{RoundStat, Status};
+use anyhow_ext::{Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use flate2::read::MultiGzDecoder;
+use log::info;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::time::Duration;
+
+/// 离线回放 eslworking.log，重建每一轮更新
+///
+/// 日志里没有下发记录，收到 receive 的价签算作本轮下发；
+/// 已经完成的价签再次 receive 时认为开始了新的一轮
+pub struct Replay {
+    log: EslLog,
+    uc: Option<String>, // 只看这个usercode
+    status: Status,
+    rounds: Vec<RoundStat>,
+    cur: Option<Round>,
+    start: Option<NaiveDateTime>, // 本轮第一条事件时间
+    last: Option<NaiveDateTime>,  // 本轮最后一条事件时间
+}
+
+impl Replay {
+    pub fn new(uc: Option<String>) -> Self {
+        Self {
+            log: EslLog::new(),
+            uc,
+            status: Status::default(),
+            rounds: Vec::new(),
+            cur: None,
+            start: None,
+            last: None,
+        }
+    }
+
+    /// 处理一行日志，没有时间的行无法计算耗时，跳过
+    pub fn feed(&mut self, line: &str) {
+        let Some(ev) = self.log.parse(line) else {
+            return;
+        };
+        if self.uc.as_ref().is_some_and(|uc| *uc != ev.user_code) {
+            return;
+        }
+        let Some(t) = ev.time else {
+            return;
+        };
+
+        let again = self
+            .cur
+            .as_ref()
+            .is_some_and(|r| r.finished.contains_key(&ev.eslid));
+        if ev.action == EslAction::Receive && again {
+            self.close();
+        }
+        let round = self.cur.get_or_insert_with(|| Round::at(t));
+        self.start.get_or_insert(t);
+        self.last = Some(t);
+        round.sent.insert(ev.eslid.clone());
+        round.on_event(&ev);
+    }
+
+    // 结束当前轮次，和实时循环一样判断结果，还有没结束的价签按超时处理
+    fn close(&mut self) {
+        let Some(round) = self.cur.take() else {
+            return;
+        };
+        let (start, last) = (self.start.take(), self.last.take());
+        let secs = match (start, last) {
+            (Some(s), Some(l)) => (l - s).num_seconds().max(0) as u64,
+            _ => 0,
+        };
+        let failed = round.stragglers();
+        let end = round.check(100, Duration::MAX).unwrap_or(RoundEnd::Timeout);
+        self.status.start_round(0, round.sent.len());
+        let mut stat = self.status.close_round(&round, &failed, end, secs);
+        // 用日志里的时间
+        stat.start = start.and_then(|t| Local.from_local_datetime(&t).single());
+        if let Some(end) = last.and_then(|t| Local.from_local_datetime(&t).single()) {
+            stat.end = end;
+        }
+        stat.price = 0;
+        self.rounds.push(stat);
+    }
+
+    /// 结束回放，返回每轮结果和汇总
+    pub fn finish(mut self) -> (Vec<RoundStat>, Report) {
+        self.close();
+        let report = Report::from_status(&self.status);
+        (self.rounds, report)
+    }
+}
+
+// .gz 结尾的按gzip读取
+fn open(fp: &str) -> Result<Box<dyn Read>> {
+    let file = File::open(fp).with_context(|| format!("open {} failed", fp))?;
+    if fp.ends_with(".gz") {
+        Ok(Box::new(MultiGzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// 按给出的顺序读取日志文件，旧的文件放在前面
+pub fn analyze(files: &[String], uc: Option<String>) -> Result<(Vec<RoundStat>, Report)> {
+    let mut replay = Replay::new(uc);
+    for fp in files {
+        info!("analyze {}", fp);
+        let mut reader = BufReader::new(open(fp)?);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader
+                .read_until(b'\n', &mut buf)
+                .with_context(|| format!("read {} failed", fp))?
+                == 0
+            {
+                break;
+            }
+            replay.feed(&String::from_utf8_lossy(&buf));
+        }
+    }
+    Ok(replay.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Replay;
+    use crate::round::RoundEnd;
+
+    fn line(t: &str, action: &str, esl: &str) -> String {
+        match action {
+            "receive" => format!("2024-11-10 {}.000 category=esl,action=receive,user_code=god.1,eslid={},payload_type=UPDATE,payload_retry_time=0", t, esl),
+            status => format!("2024-11-10 {}.000 category=esl,action=esl_update_finished,user_code=god.1,eslid={},status={}", t, esl, status),
+        }
+    }
+
+    #[test]
+    fn test_replay_rounds() {
+        let mut replay = Replay::new(Some("god.1".to_string()));
+        for l in [
+            line("10:00:00", "receive", "A"),
+            line("10:00:01", "receive", "B"),
+            line("10:00:10", "success", "A"),
+            line("10:00:20", "failed", "B"),
+            // A 再次收到，第二轮
+            line("10:05:00", "receive", "A"),
+            line("10:05:01", "receive", "B"),
+            line("10:05:05", "success", "A"),
+            line("10:05:08", "success", "B"),
+        ] {
+            replay.feed(&l);
+        }
+        let (rounds, report) = replay.finish();
+
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].result, RoundEnd::Failed);
+        assert_eq!(rounds[0].failed, vec!["B".to_string()]);
+        assert_eq!(rounds[0].secs, 20);
+        assert_eq!(rounds[1].result, RoundEnd::All);
+        assert_eq!(rounds[1].finished, 2);
+        assert_eq!(report.rounds, 2);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.esls[0].esl, "B");
+        assert_eq!(report.esls[0].avg_ms, Some(8000));
+        assert_eq!(report.esls[1].avg_ms, Some(7500));
+    }
+}
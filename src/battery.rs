@@ -0,0 +1,71 @@
+use anyhow_ext::{Context, Result};
+use log::info;
+use regex::Regex;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+
+/// 获取电池电量信息，从ew api日志的 file_max_seek 位置开始读，追加写到 out，返回写入条数
+pub fn get_battery_info(
+    esl_ids: &[String],
+    api_log_fp: &str,
+    file_max_seek: u64,
+    out: &str,
+) -> Result<usize> {
+    // 打开 battery_fp 文件，以追加模式
+    let mut battery_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(out)
+        .with_context(|| format!("can't open or create file : {}", out))?;
+
+    info!("write battery start");
+
+    // 预编译正则表达式
+    let re = Regex::new(r",query_type=53,battery=(.*?),sid=").expect("正则表达式编译失败");
+
+    // 打开 api_log_fp 文件，以只读模式
+    let api_log_file = OpenOptions::new()
+        .read(true)
+        .open(api_log_fp)
+        .with_context(|| format!("无法打开文件: {}", api_log_fp))?;
+
+    let mut reader = BufReader::new(api_log_file);
+    reader
+        .seek(SeekFrom::Start(file_max_seek))
+        .context("文件定位失败")?;
+
+    // 逐行读取日志文件
+    let mut count = 0;
+    for line in reader.lines() {
+        let line = line.context("读取日志文件失败")?;
+        // 检查是否包含特定关键词
+        if !line.contains("category=api,action=prepare_ack,cmd=ESL_STATISTICS_QUERY_ACK") {
+            continue;
+        }
+        for esl in esl_ids {
+            if !line.contains(&format!("esl_id={}", esl)) {
+                continue;
+            }
+            if let Some(caps) = re.captures(&line) {
+                let battery_power = caps
+                    .get(1)
+                    .map_or("0".to_string(), |m| m.as_str().to_string());
+
+                // 假设日期时间信息位于行首 23 个字符
+                let dt = line.get(..23).unwrap_or("none");
+
+                // 记录电池信息到文件
+                writeln!(
+                    battery_file,
+                    "{} - esl={};battery={}",
+                    dt, esl, battery_power
+                )
+                .context("写入电池信息失败")?;
+                info!("{} - esl={};battery={}", dt, esl, battery_power);
+                count += 1;
+            }
+        }
+    }
+    info!("write battery finish");
+    Ok(count)
+}
@@ -0,0 +1,225 @@
+use crate::metrics;
+use crate::status::SharedStatus;
+use anyhow_ext::Result;
+use axum::extract::State;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+/// 收到退出信号后，等待已下发价签完成的最长时间 s
+pub const SHUTDOWN_TIMEOUT: u64 = 60;
+
+/// 控制信号，http接口写入，更新循环读取
+#[derive(Debug, Default)]
+pub struct Control {
+    pub paused: AtomicBool,   // 暂停下发
+    pub trigger: AtomicBool,  // 立即开始下一轮
+    pub reload: AtomicBool,   // 下一轮前重新读取价签列表
+    pub interval: AtomicU64,  // 轮次间隔 s，0 表示用配置
+    pub shutdown: AtomicBool, // 收到退出信号，不再下发
+    pub resting: AtomicBool,  // 休眠时间段暂停中，等日志切割
+    wake: Notify,
+}
+
+pub type SharedControl = Arc<Control>;
+
+impl Control {
+    /// 可被trigger/resume/退出打断的休眠
+    pub async fn sleep(&self, dur: Duration) {
+        let wake = self.wake.notified();
+        tokio::pin!(wake);
+        // 先登记再检查，检查之后的通知不会丢
+        wake.as_mut().enable();
+        if self.trigger.load(Ordering::Relaxed) || self.is_shutdown() {
+            return;
+        }
+        tokio::select! {
+            _ = sleep(dur) => {}
+            _ = wake => {}
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        self.notify();
+    }
+
+    /// 立即开始下一轮，打断正在等待的休眠
+    pub fn trigger_now(&self) {
+        self.trigger.store(true, Ordering::Relaxed);
+        self.notify();
+    }
+
+    pub fn take_trigger(&self) -> bool {
+        self.trigger.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn take_reload(&self) -> bool {
+        self.reload.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// 通知循环退出，打断正在等待的休眠
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        self.notify();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// 轮次间隔，接口设置过就用接口的
+    pub fn interval_or(&self, default: u64) -> u64 {
+        match self.interval.load(Ordering::Relaxed) {
+            0 => default,
+            v => v,
+        }
+    }
+
+    /// 等待下一轮：先等间隔，暂停时一直等到恢复，trigger 和退出会跳过等待
+    pub async fn wait_next(&self, interval: u64) {
+        if interval > 0 && !self.trigger.load(Ordering::Relaxed) && !self.is_shutdown() {
+            self.sleep(Duration::from_secs(interval)).await;
+        }
+        while self.is_paused() && !self.trigger.load(Ordering::Relaxed) && !self.is_shutdown() {
+            self.sleep(Duration::from_secs(1)).await;
+        }
+        self.take_trigger();
+    }
+
+    fn notify(&self) {
+        self.wake.notify_waiters();
+    }
+}
+
+// Ctrl-C 或 SIGTERM
+async fn stop_signal() {
+    #[cfg(unix)]
+    {
+        let mut term = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("listen SIGTERM failed");
+        tokio::select! {
+            _ = signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signal::ctrl_c().await;
+}
+
+/// 第一次收到退出信号时通知循环收尾，第二次直接退出
+pub fn watch_signal(ctrl: SharedControl) {
+    tokio::spawn(async move {
+        stop_signal().await;
+        warn!("receive stop signal, stop sending and wait in-flight esl");
+        ctrl.shutdown();
+        stop_signal().await;
+        warn!("receive stop signal again, exit now");
+        std::process::exit(1);
+    });
+}
+
+#[derive(Clone)]
+struct AppState {
+    status: SharedStatus,
+    ctrl: SharedControl,
+}
+
+#[derive(Deserialize)]
+struct IntervalReq {
+    secs: u64,
+}
+
+/// 启动控制接口
+pub async fn serve(addr: String, status: SharedStatus, ctrl: SharedControl) -> Result<()> {
+    let app = Router::new()
+        .route("/status", get(get_status))
+        .route("/rounds/last", get(last_round))
+        .route("/esls", get(esls))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/trigger", post(trigger))
+        .route("/interval", put(set_interval))
+        .route("/reload", post(reload))
+        .route("/metrics", get(get_metrics))
+        .with_state(AppState { status, ctrl });
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("control api listen on {}", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn get_status(State(st): State<AppState>) -> Json<Value> {
+    let s = st.status.lock().unwrap();
+    Json(json!({
+        "round": s.round,
+        "price": s.price,
+        "round_start": s.round_start,
+        "sent": s.sent,
+        "received": s.received,
+        "finished": s.finished,
+        "failed": s.failed,
+        "paused": st.ctrl.is_paused(),
+        "resting": st.ctrl.resting.load(Ordering::Relaxed),
+        "interval": st.ctrl.interval.load(Ordering::Relaxed),
+    }))
+}
+
+async fn last_round(State(st): State<AppState>) -> Json<Value> {
+    Json(json!(st.status.lock().unwrap().last))
+}
+
+async fn esls(State(st): State<AppState>) -> Json<Value> {
+    Json(json!(st.status.lock().unwrap().esls))
+}
+
+async fn pause(State(st): State<AppState>) -> Json<Value> {
+    st.ctrl.pause();
+    info!("control: pause");
+    Json(json!({"paused": true}))
+}
+
+async fn resume(State(st): State<AppState>) -> Json<Value> {
+    st.ctrl.resume();
+    info!("control: resume");
+    Json(json!({"paused": false}))
+}
+
+async fn trigger(State(st): State<AppState>) -> Json<Value> {
+    st.ctrl.trigger_now();
+    info!("control: trigger round now");
+    Json(json!({"trigger": true}))
+}
+
+async fn set_interval(State(st): State<AppState>, Json(req): Json<IntervalReq>) -> Json<Value> {
+    st.ctrl.interval.store(req.secs, Ordering::Relaxed);
+    info!("control: interval={}s", req.secs);
+    Json(json!({"interval": req.secs}))
+}
+
+async fn reload(State(st): State<AppState>) -> Json<Value> {
+    st.ctrl.reload.store(true, Ordering::Relaxed);
+    info!("control: reload esl list on next round");
+    Json(json!({"reload": true}))
+}
+
+async fn get_metrics() -> String {
+    metrics::gather()
+}
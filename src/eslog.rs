@@ -0,0 +1,111 @@
+use anyhow_ext::Result;
+use chrono::NaiveDateTime;
+use log::info;
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+/// eslworking.log 中价签的动作
+#[derive(Debug, Clone, PartialEq)]
+pub enum EslAction {
+    Receive,        // 价签收到更新
+    Finish(String), // 更新结束，带status
+}
+
+impl EslAction {
+    /// 更新结束但status不是success，没有status的按成功算
+    pub fn failed(&self) -> bool {
+        matches!(self, Self::Finish(status) if !status.is_empty() && status != "success")
+    }
+}
+
+/// 从一行日志中解析出的价签事件
+#[derive(Debug, Clone)]
+pub struct EslEvent {
+    pub action: EslAction,
+    pub user_code: String,
+    pub eslid: String,
+    pub time: Option<NaiveDateTime>, // 行首的日志时间
+}
+
+/// eslworking.log 行解析
+pub struct EslLog {
+    receive_re: Regex,
+    release_re: Regex,
+}
+
+impl Default for EslLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EslLog {
+    pub fn new() -> Self {
+        let receive_re = Regex::new(r"category=esl,action=receive,user_code=(.*),eslid=(.*),payload_type=UPDATE,payload_retry_time=").unwrap();
+        let release_re = Regex::new(
+            r"category=esl,action=esl_update_finished,user_code=(.*),eslid=(.*),status=([^,\s]*)",
+        )
+        .unwrap();
+        Self {
+            receive_re,
+            release_re,
+        }
+    }
+
+    /// 解析一行日志，不是receive/finish的行返回None
+    pub fn parse(&self, line: &str) -> Option<EslEvent> {
+        let (action, captures) = if let Some(c) = self.receive_re.captures(line) {
+            (EslAction::Receive, c)
+        } else if let Some(c) = self.release_re.captures(line) {
+            let status = c.get(3).map_or("", |m| m.as_str()).to_string();
+            (EslAction::Finish(status), c)
+        } else {
+            return None;
+        };
+
+        let eslid = captures.get(2)?.as_str().to_string();
+        if eslid.is_empty() {
+            return None;
+        }
+        Some(EslEvent {
+            action,
+            user_code: captures.get(1)?.as_str().to_string(),
+            eslid,
+            time: parse_time(line),
+        })
+    }
+
+    /// 从 seek 位置读新写入的日志，逐个交给 f，没写完的行下次再读；
+    /// 文件比 seek 小说明日志被切割了，从头读取。返回文件长度
+    pub fn tail(&self, fp: &str, seek: &mut u64, mut f: impl FnMut(EslEvent)) -> Result<u64> {
+        let file = File::open(fp)?;
+        let file_len = file.metadata()?.len();
+        if file_len < *seek {
+            info!("{} rotated, read from start; seek={}", fp, seek);
+            *seek = 0;
+        }
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(*seek))?;
+        loop {
+            let mut buf = Vec::new();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 || !buf.ends_with(b"\n") {
+                break;
+            }
+            *seek += n as u64;
+            if let Some(ev) = self.parse(&String::from_utf8_lossy(&buf)) {
+                f(ev);
+            }
+        }
+        Ok(file_len)
+    }
+}
+
+// 假设日期时间信息位于行首 23 个字符
+fn parse_time(line: &str) -> Option<NaiveDateTime> {
+    let dt = line.get(..23)?;
+    NaiveDateTime::parse_from_str(dt, "%Y-%m-%d %H:%M:%S%.3f")
+        .or_else(|_| NaiveDateTime::parse_from_str(dt.get(..19).unwrap_or(dt), "%Y-%m-%d %H:%M:%S"))
+        .ok()
+}
@@ -0,0 +1,463 @@
+use crate::ctrl::SharedControl;
+use crate::hook::{HookConf, HookEvent, Hooks};
+use crate::metrics;
+use crate::mock::{self, MockConf};
+use crate::pic::make_auto_pic;
+use crate::round::Round;
+use crate::status::SharedStatus;
+use anyhow_ext::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveTime, Timelike};
+use log::info;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::{self};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// 日志超过这个大小，在休眠时间段内等待ew切割日志
+pub const ROTATE_SIZE: u64 = 1048576000;
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Page {
+    id: u32,
+    name: String,
+    image: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Screen {
+    name: String,
+    default_page: String,
+    default_page_id: String,
+    pages: Vec<Page>,
+}
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ESLupdate {
+    sid: String,
+    priority: u32,
+    esl_id: String,
+    back_url: String,
+    screen: Screen,
+}
+
+impl fmt::Display for ESLupdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ESLupdate {{ sid: {}, priority: {}, esl_id: {}, back_url: {} }}",
+            self.sid, self.priority, self.esl_id, self.back_url
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EwConf {
+    pub api: String,            // ewapi
+    pub uc: String,             // usercode
+    pub back_url: String,       // back url
+    pub epd_wl: String,         //
+    pub ewlog: String,          // ew 日志
+    pub startprice: i32,        // 开始的价格
+    pub limittime: [String; 2], // 休眠时间
+    #[serde(skip_serializing, skip_deserializing)]
+    pub esl_id_list: Vec<String>, // 要更新的epd
+    #[serde(skip_serializing, skip_deserializing)]
+    pub starttime: Option<DateTime<Local>>, // 开始时间
+    #[serde(skip_serializing, skip_deserializing)]
+    pub fileseek: u64, // 文件指针位置
+    pub template: Option<String>, // 自定义更细模版文件夹
+    pub auto: Option<bool>,     // 是否不查询日志
+    pub autotime: Option<u64>,  // 定时更新 s
+    pub roundtimeout: Option<u64>, // 单轮超时 s
+    pub finishrate: Option<u32>, // 完成比例 %，达到后开始下一轮
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) round: Option<Round>, // 当前轮次
+    pub tui: Option<bool>,      // 是否显示终端界面
+    #[serde(skip_serializing, skip_deserializing)]
+    pub status: SharedStatus, // 运行状态
+    pub ctrl_addr: Option<String>, // 控制接口监听地址
+    #[serde(skip_serializing, skip_deserializing)]
+    pub ctrl: SharedControl, // 控制信号
+    pub mock: Option<MockConf>, // dry-run 时模拟ew的配置
+    pub pollms: Option<u64>,    // 日志轮询间隔 ms
+    pub history: Option<String>, // 轮次记录文件
+    pub shutdowntimeout: Option<u64>, // 退出时等待价签完成 s
+    pub report: Option<String>, // 退出时的汇总报告文件
+    pub hooks: Option<HookConf>, // 事件通知
+    #[serde(skip_serializing, skip_deserializing)]
+    pub hook: Hooks,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub clock: Option<NaiveTime>, // 固定当前时间，测试休眠时间段用
+}
+
+pub(crate) struct RunTime {
+    pub st: DateTime<Local>,
+    pub et: DateTime<Local>,
+}
+
+impl RunTime {
+    pub fn timediff(&self) -> Duration {
+        // 计算时间差，得到的是一个 `chrono::Duration`，用完整时间跨零点也不会出错
+        let duration = self.et.signed_duration_since(self.st);
+        // 将 `chrono::Duration` 转换为 `std::time::Duration`，时钟回拨时为0
+        duration.to_std().unwrap_or_default()
+    }
+}
+// 计算休眠时间
+pub fn need_sleep_time(args: &[String; 2]) -> u64 {
+    let time1_str = &args[0];
+    let time2_str = &args[1];
+    let time1 = NaiveTime::parse_from_str(time1_str, "%H:%M").expect("cant't");
+    let time2 = NaiveTime::parse_from_str(time2_str, "%H:%M").expect("ÎÞ·¨½âÎöÊ±¼ä");
+    let seconds1 = time1.num_seconds_from_midnight();
+    let seconds2 = time2.num_seconds_from_midnight();
+    (seconds2 - seconds1) as u64
+}
+
+// 生成制定长度的随机字符串 ，用来sid
+pub(crate) fn generate_random_string(length: usize) -> String {
+    // 创建一个线程安全的随机数生成器
+    let mut rng = thread_rng();
+    // 生成指定长度的随机字符串
+    (0..length)
+        .map(|_| rng.sample(Alphanumeric))
+        .map(char::from)
+        .collect()
+}
+
+// 获取初始文件seek
+pub fn get_eslwlog_seek(fp: &str) -> Result<u64> {
+    let mut file = File::open(fp)?;
+    let position = file.seek(SeekFrom::End(0))?;
+    info!("start file seek ={}", position);
+    Ok(position)
+}
+
+/// 获取id
+pub fn get_esl_id_out(fp: &String, uc: &String) -> Result<Vec<String>> {
+    let file = File::open(fp).expect("epd_wl is not found,pls check");
+    let reader = io::BufReader::new(file);
+
+    let uc_suffix = format!("={}", uc);
+    let esl_list: Vec<String> = reader
+        .lines()
+        .map_while(|line| line.ok())
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.replace("\n", "").replace(&uc_suffix, ""))
+        .collect();
+    info!("esl list len = {}", esl_list.len());
+    Ok(esl_list)
+}
+
+impl EwConf {
+    /// 读取配置文件，并读取价签列表和日志位置
+    pub fn load(fp: &str) -> Self {
+        Self::from_conf(Self::read_conf(fp))
+    }
+
+    /// 只读取配置文件
+    pub fn read_conf(fp: &str) -> Self {
+        let _f = File::open(fp).expect("conf.txt is not found"); // 打开文件
+        let reader = BufReader::new(_f); // 创建一个带缓冲区的读取器
+        serde_json::from_reader(reader).unwrap()
+    }
+
+    /// dry-run：启动本地模拟ew，api和日志都指向它
+    pub async fn dry_run(fp: &str) -> Self {
+        let mut conf_info = Self::read_conf(fp);
+        let mock_conf = conf_info.mock.clone().unwrap_or_default();
+        let ew = mock::start(mock_conf.clone())
+            .await
+            .expect("start mock ew failed");
+        mock::fake_esl_list(&conf_info.epd_wl, 20).expect("write fake esl list failed");
+        info!("dry run, ew api={}, ew log={}", ew.addr, mock_conf.ewlog);
+        conf_info.api = ew.addr.to_string();
+        conf_info.ewlog = mock_conf.ewlog;
+        Self::from_conf(conf_info)
+    }
+
+    /// 根据配置读取价签列表和日志位置
+    pub fn from_conf(conf_info: EwConf) -> Self {
+        let esl_id_list_ = get_esl_id_out(&conf_info.epd_wl, &conf_info.uc).unwrap();
+        let start_fileseek = get_eslwlog_seek(&conf_info.ewlog).expect("ew log path not found");
+        let tpt = conf_info.template;
+
+        if conf_info.auto.unwrap_or(false) && conf_info.autotime.is_none() {
+            panic!("配置错误：当 `auto` 为 true 时，`autotime` 不能为空");
+        }
+        let autotime = conf_info.autotime;
+        if conf_info.finishrate.is_some_and(|r| r == 0 || r > 100) {
+            panic!("配置错误：`finishrate` 取值 1-100");
+        }
+        Self {
+            api: conf_info.api,
+            uc: conf_info.uc,
+            back_url: conf_info.back_url,
+            epd_wl: conf_info.epd_wl,
+            ewlog: conf_info.ewlog,
+            startprice: conf_info.startprice,
+            limittime: conf_info.limittime,
+            esl_id_list: esl_id_list_,
+            starttime: None,
+            fileseek: start_fileseek,
+            template: tpt,        // 自定义更新模版
+            auto: conf_info.auto, // 间隔日志
+            autotime,             // 间隔时间 s
+            roundtimeout: conf_info.roundtimeout,
+            finishrate: conf_info.finishrate,
+            round: None,
+            tui: conf_info.tui,
+            status: SharedStatus::default(),
+            ctrl_addr: conf_info.ctrl_addr,
+            ctrl: SharedControl::default(),
+            mock: conf_info.mock,
+            pollms: conf_info.pollms,
+            history: conf_info.history,
+            shutdowntimeout: conf_info.shutdowntimeout,
+            report: conf_info.report,
+            hook: Hooks::new(conf_info.hooks.clone().unwrap_or_default()),
+            hooks: conf_info.hooks,
+            clock: None,
+        }
+    }
+
+    pub fn is_during(now: NaiveTime, start_time_: &str, end_time_: &str) -> bool {
+        let mut status = false;
+        let start_time = NaiveTime::parse_from_str(start_time_, "%H:%M").unwrap();
+        let end_time = NaiveTime::parse_from_str(end_time_, "%H:%M").unwrap();
+        if now >= start_time && now <= end_time {
+            info!("check time pass ");
+            status = true;
+        }
+        status
+    }
+
+    /// 休眠时间段内并且日志够大，需要暂停等日志切割
+    pub fn need_pause(&self, now: NaiveTime) -> bool {
+        Self::is_during(now, &self.limittime[0], &self.limittime[1]) && self.fileseek > ROTATE_SIZE
+    }
+
+    //  获取eslid的尺寸用来的自定义模版，返回{"eslid":"templatename"}
+    pub async fn get_esl_id_size(&mut self, esl: &Vec<String>) -> Result<HashMap<String, String>> {
+        //127.0.0.1:9000/api3/esls/36-F0-BF-8B
+        let cli = Client::new();
+        let mut tmpresult: HashMap<String, String> = HashMap::new();
+        for ev in esl {
+            let data: HashMap<String, Value> = cli
+                .get(format!("http://{a}/api3/esls/{e}", a = self.api, e = ev))
+                .send()
+                .await?
+                .json()
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&data)?);
+            let picname = data
+                .get("data")
+                .ok_or(anyhow!("can't find key data"))?
+                .get("description")
+                .ok_or(anyhow!("no exist description"))?;
+            tmpresult.insert(String::from(ev), picname.to_string());
+        }
+        info!("final hashmap ={}", serde_json::to_string(&tmpresult)?);
+        Ok(tmpresult)
+    }
+
+    /// 读取eslid问题
+    pub fn get_esl_id(&mut self) -> Result<Vec<String>> {
+        let file = File::open(&self.epd_wl).unwrap();
+        let reader = io::BufReader::new(file);
+
+        let uc_suffix = format!("={}", self.uc);
+        let esl_list: Vec<String> = reader
+            .lines()
+            .map_while(|line| line.ok())
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.replace("\n", "").replace(&uc_suffix, ""))
+            .collect();
+
+        info!("esl list len = {}", esl_list.len());
+        Ok(esl_list)
+    }
+
+    pub async fn update(&mut self) -> Result<()> {
+        // 每次下发开始新的一轮
+        self.round = Some(Round::new(&self.esl_id_list));
+        self.status
+            .lock()
+            .unwrap()
+            .start_round(self.startprice, self.esl_id_list.len());
+        let sent = if self.template.is_some() {
+            self.update_tpl().await
+        } else {
+            self.update_pic().await
+        };
+        let sent = sent.inspect_err(|e| {
+            self.hook.fire(HookEvent::ApiUnreachable {
+                api: self.api.clone(),
+                error: e.to_string(),
+            })
+        })?;
+        // 下发中途收到退出信号，没发出去的价签不算
+        if sent < self.esl_id_list.len() {
+            if let Some(round) = self.round.as_mut() {
+                round.keep(&self.esl_id_list[..sent]);
+            }
+            self.status.lock().unwrap().keep(sent);
+        }
+        Ok(())
+    }
+
+    // 下发更新
+    async fn update_tpl(&mut self) -> Result<usize> {
+        let mut sent = 0;
+        // 将 esl_id_list 按每 200 个一组分块处理
+        for esl_chunk in self.esl_id_list.chunks(200) {
+            if self.ctrl.is_shutdown() {
+                info!("shutdown, stop sending; sent={}", sent);
+                break;
+            }
+            let mut batch = Vec::new();
+            // 为当前块的每个 esl_id 创建 ESLupdate 并添加到 batch
+            for e in esl_chunk {
+                let d = json!({
+                    "sid": generate_random_string(12),
+                    "esl_id": e,
+                    "priority": 1,
+                    "back_url": self.back_url,
+                    "store_name": self.uc,
+                    "price": self.startprice,
+                    "template": self.template,
+                });
+                batch.push(d);
+            }
+            // 构建请求数据
+            let data = json!({ "data": batch });
+            let client = Client::new();
+            let response: reqwest::Response = client
+                .put(format!("http://{}/api3/{}/esls", self.api, self.uc))
+                .json(&data)
+                .send()
+                .await
+                .map_err(|e| {
+                    metrics::HTTP_ERRORS.with_label_values(&["connect"]).inc();
+                    anyhow_ext::Error::from(e)
+                })?;
+
+            // 检查请求是否成功
+            if response.status().is_success() {
+                metrics::UPDATES_SENT.inc_by(esl_chunk.len() as u64);
+                info!("Request was successful for a batch of 200!");
+            } else {
+                metrics::HTTP_ERRORS
+                    .with_label_values(&[response.status().as_str()])
+                    .inc();
+                info!("Request failed with status: {}", response.status());
+            }
+            sent += esl_chunk.len();
+            // 每批发送完成后休眠一段时间，避免请求过快
+            sleep(Duration::from_millis(200)).await;
+        }
+
+        // 记录开始时间和价格更新
+        self.starttime = Some(Local::now());
+        info!(
+            "Update send over and price is {}; update start time = {:?} ",
+            self.startprice, &self.starttime
+        );
+        self.startprice += 1; // 价格增加
+        Ok(sent)
+    }
+
+    // 下发更新
+    async fn update_pic(&mut self) -> Result<usize> {
+        let mut sent = 0;
+        // 将按每 15 个一组分块处理，要不太快
+        let count = 15 ; 
+        let sid_info = generate_random_string(12);
+        let img_data = make_auto_pic(self.startprice);
+        for esl_chunk in self.esl_id_list.chunks(count) {
+            if self.ctrl.is_shutdown() {
+                info!("shutdown, stop sending; sent={}", sent);
+                break;
+            }
+            let mut batch = Vec::new();
+            for e in esl_chunk {
+                let d = json!({
+                    "sid": sid_info,
+                    "esl_id": e,
+                    "priority": 10,
+                    "back_url": self.back_url,
+                    "screen": {
+                        "name": e,
+                        "default_page": "normal",
+                        "default_page_id": "0",
+                        "pages": [
+                            {
+                                "id": 0,
+                                "name": "normal",
+                                "image": img_data
+                            },
+                        ]
+                    },
+                });
+                batch.push(d);
+            }
+            // 构建请求数据
+            let data = json!({ "data": batch });
+            let client = Client::new();
+            let response: reqwest::Response = client
+                .put(format!("http://{}/api3/{}/esls", self.api, self.uc))
+                .json(&data)
+                .send()
+                .await
+                .map_err(|e| {
+                    metrics::HTTP_ERRORS.with_label_values(&["connect"]).inc();
+                    anyhow_ext::Error::from(e)
+                })?;
+            // 检查请求是否成功
+            if response.status().is_success() {
+                metrics::UPDATES_SENT.inc_by(esl_chunk.len() as u64);
+                info!("Request was successful for a batch of {}!", count);
+            } else {
+                metrics::HTTP_ERRORS
+                    .with_label_values(&[response.status().as_str()])
+                    .inc();
+                info!("Request failed with status: {}", response.status());
+            }
+            sent += esl_chunk.len();
+            // 每批发送完成后休眠一段时间，避免请求过快
+            sleep(Duration::from_millis(300)).await;
+        }
+
+        // 记录开始时间和价格更新
+        self.starttime = Some(Local::now());
+        info!(
+            "Update pic send over and price is {}; update start time = {:?} ",
+            self.startprice, &self.starttime
+        );
+        self.startprice += 1; // 价格增加
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RunTime;
+    use chrono::{Local, TimeZone};
+    use std::time::Duration;
+
+    #[test]
+    fn test_timediff_cross_midnight() {
+        let st = Local.with_ymd_and_hms(2024, 11, 10, 23, 59, 0).unwrap();
+        let et = Local.with_ymd_and_hms(2024, 11, 11, 0, 1, 30).unwrap();
+        assert_eq!(RunTime { st, et }.timediff(), Duration::from_secs(150));
+        assert_eq!(RunTime { st: et, et: st }.timediff(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::status::RoundStat;
+use anyhow_ext::Result;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// 每轮结果，一行一个json
+pub const HISTORY_FILE: &str = "log/history.jsonl";
+
+/// 追加一轮结果
+pub fn append(fp: &str, stat: &RoundStat) -> Result<()> {
+    if let Some(dir) = Path::new(fp).parent() {
+        create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(fp)?;
+    writeln!(file, "{}", serde_json::to_string(stat)?)?;
+    Ok(())
+}
+
+/// 读取全部轮次，解析不了的行跳过
+pub fn load(fp: &str) -> Result<Vec<RoundStat>> {
+    let reader = BufReader::new(File::open(fp)?);
+    let rounds = reader
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect();
+    Ok(rounds)
+}
+
+/// history 子命令，输出最近 last 轮
+pub fn print(fp: &str, last: Option<usize>) -> Result<()> {
+    let rounds = load(fp)?;
+    let skip = last.map_or(0, |n| rounds.len().saturating_sub(n));
+    print_rounds(&rounds[skip..]);
+    Ok(())
+}
+
+/// 按表格输出轮次
+pub fn print_rounds(rounds: &[RoundStat]) {
+    println!(
+        "{:>6} {:>6} {:<19} {:<19} {:>7} {:>6} {:>8} {:>7}",
+        "round", "price", "start", "end", "second", "esl", "finished", "result"
+    );
+    for r in rounds {
+        let start = r.start.map_or("-".to_string(), |t| {
+            t.format("%Y-%m-%d %H:%M:%S").to_string()
+        });
+        println!(
+            "{:>6} {:>6} {:<19} {:<19} {:>7} {:>6} {:>8} {:>7}",
+            r.round,
+            r.price,
+            start,
+            r.end.format("%Y-%m-%d %H:%M:%S"),
+            r.secs,
+            r.sent,
+            r.finished,
+            format!("{:?}", r.result)
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::round::RoundEnd;
+use crate::status::RoundStat;
+use anyhow_ext::{anyhow, Result};
+use chrono::Local;
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// 通知发送超时 s
+const HOOK_TIMEOUT: u64 = 10;
+
+/// 取日志命令超时 s，要等基站上传
+const COLLECT_TIMEOUT: u64 = 300;
+
+/// 本地smtp中继
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmtpConf {
+    pub addr: String,    // 127.0.0.1:25
+    pub from: String,    // 发件人
+    pub to: Vec<String>, // 收件人
+}
+
+/// 通知配置，三种方式可以同时用
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HookConf {
+    pub webhook: Option<String>, // POST json
+    pub command: Option<String>, // sh -c 执行，事件放在环境变量
+    pub smtp: Option<SmtpConf>,
+    pub collect: Option<String>, // 失败事件时执行的取日志命令，如 update log fetch --aps ap.txt --store org/store
+    pub minrate: Option<u32>,    // 一轮完成比例低于这个值 % 通知
+    pub failstreak: Option<u32>, // 价签连续失败这么多轮通知
+    pub events: Vec<String>,     // 只通知这些事件，空为全部
+}
+
+/// 更新过程中的事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HookEvent {
+    RoundCompleted {
+        round: u32,
+        sent: usize,
+        finished: usize,
+        secs: u64,
+    },
+    RoundTimeout {
+        round: u32,
+        sent: usize,
+        finished: usize,
+        secs: u64,
+    },
+    RoundFailed {
+        round: u32,
+        sent: usize,
+        finished: usize,
+        failed: usize,
+        secs: u64,
+    },
+    RoundStopped {
+        round: u32,
+        sent: usize,
+        finished: usize,
+        secs: u64,
+    },
+    LowRate {
+        round: u32,
+        rate: f64,
+        minrate: u32,
+    },
+    ApiUnreachable {
+        api: String,
+        error: String,
+    },
+    EslFailing {
+        esl: String,
+        rounds: u32,
+    },
+}
+
+impl HookEvent {
+    /// 一轮结束的事件，按结束原因区分
+    pub fn round(stat: &RoundStat) -> Self {
+        let (round, sent, finished, secs) = (stat.round, stat.sent, stat.finished, stat.secs);
+        match stat.result {
+            RoundEnd::Timeout => Self::RoundTimeout {
+                round,
+                sent,
+                finished,
+                secs,
+            },
+            RoundEnd::Failed => Self::RoundFailed {
+                round,
+                sent,
+                finished,
+                failed: stat.failed.len(),
+                secs,
+            },
+            RoundEnd::Shutdown => Self::RoundStopped {
+                round,
+                sent,
+                finished,
+                secs,
+            },
+            RoundEnd::All | RoundEnd::Rate | RoundEnd::Manual => Self::RoundCompleted {
+                round,
+                sent,
+                finished,
+                secs,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RoundCompleted { .. } => "round_completed",
+            Self::RoundTimeout { .. } => "round_timeout",
+            Self::RoundFailed { .. } => "round_failed",
+            Self::RoundStopped { .. } => "round_stopped",
+            Self::LowRate { .. } => "low_rate",
+            Self::ApiUnreachable { .. } => "api_unreachable",
+            Self::EslFailing { .. } => "esl_failing",
+        }
+    }
+
+    /// 除了一轮正常完成和退出时中止，其他都算失败
+    pub fn failed(&self) -> bool {
+        !matches!(
+            self,
+            Self::RoundCompleted { .. } | Self::RoundStopped { .. }
+        )
+    }
+
+    /// 一行说明，邮件主题和命令环境变量用
+    pub fn message(&self) -> String {
+        match self {
+            Self::RoundCompleted {
+                round,
+                sent,
+                finished,
+                secs,
+            } => format!(
+                "round {} completed, finish={}/{}, use {}s",
+                round, finished, sent, secs
+            ),
+            Self::RoundTimeout {
+                round,
+                sent,
+                finished,
+                secs,
+            } => format!(
+                "round {} timeout, finish={}/{}, use {}s",
+                round, finished, sent, secs
+            ),
+            Self::RoundFailed {
+                round,
+                sent,
+                finished,
+                failed,
+                secs,
+            } => format!(
+                "round {} failed, finish={}/{}, failed={}, use {}s",
+                round, finished, sent, failed, secs
+            ),
+            Self::RoundStopped {
+                round,
+                sent,
+                finished,
+                secs,
+            } => format!(
+                "round {} stopped by shutdown, finish={}/{}, use {}s",
+                round, finished, sent, secs
+            ),
+            Self::LowRate {
+                round,
+                rate,
+                minrate,
+            } => format!(
+                "round {} success rate {:.2}% below {}%",
+                round, rate, minrate
+            ),
+            Self::ApiUnreachable { api, error } => format!("ew api {} unreachable, {}", api, error),
+            Self::EslFailing { esl, rounds } => {
+                format!("esl {} failed {} rounds in a row", esl, rounds)
+            }
+        }
+    }
+}
+
+/// 按配置发送事件通知，发送在后台进行不阻塞更新循环
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub conf: HookConf,
+    client: Client,
+}
+
+impl Hooks {
+    pub fn new(conf: HookConf) -> Self {
+        Self {
+            conf,
+            client: Client::new(),
+        }
+    }
+
+    fn enabled(&self, ev: &HookEvent) -> bool {
+        let conf = &self.conf;
+        if conf.webhook.is_none() && conf.command.is_none() && conf.smtp.is_none() {
+            return false;
+        }
+        conf.events.is_empty() || conf.events.iter().any(|e| e == ev.name())
+    }
+
+    pub fn fire(&self, ev: HookEvent) {
+        // 取日志不受 events 限制，失败事件都执行
+        let collect = self.conf.collect.clone().filter(|_| ev.failed());
+        let notify = self.enabled(&ev);
+        if !notify && collect.is_none() {
+            return;
+        }
+        info!("hook event {}: {}", ev.name(), ev.message());
+        let hooks = self.clone();
+        tokio::spawn(async move {
+            let mut body = serde_json::to_value(&ev).unwrap_or(Value::Null);
+            body["message"] = json!(ev.message());
+            body["time"] = json!(Local::now().to_rfc3339());
+            if let Some(cmd) = &collect {
+                if let Err(e) = run_command(cmd, &ev, &body, COLLECT_TIMEOUT).await {
+                    warn!("collect log command failed, {}", e);
+                }
+            }
+            if !notify {
+                return;
+            }
+            if let Some(url) = &hooks.conf.webhook {
+                if let Err(e) = hooks.webhook(url, &body).await {
+                    warn!("webhook {} failed, {}", url, e);
+                }
+            }
+            if let Some(cmd) = &hooks.conf.command {
+                if let Err(e) = run_command(cmd, &ev, &body, HOOK_TIMEOUT).await {
+                    warn!("hook command failed, {}", e);
+                }
+            }
+            if let Some(smtp) = &hooks.conf.smtp {
+                let subject = ev.message();
+                let text = serde_json::to_string_pretty(&body).unwrap_or_default();
+                let send = send_mail(smtp, &subject, &text);
+                match timeout(Duration::from_secs(HOOK_TIMEOUT), send).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("send mail failed, {}", e),
+                    Err(_) => warn!("send mail timeout"),
+                }
+            }
+        });
+    }
+
+    async fn webhook(&self, url: &str, body: &Value) -> Result<()> {
+        let response = self
+            .client
+            .post(url)
+            .timeout(Duration::from_secs(HOOK_TIMEOUT))
+            .json(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("status {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+// 事件通过环境变量 HOOK_EVENT HOOK_MESSAGE HOOK_JSON 传给命令
+async fn run_command(cmd: &str, ev: &HookEvent, body: &Value, secs: u64) -> Result<()> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .env("HOOK_EVENT", ev.name())
+        .env("HOOK_MESSAGE", ev.message())
+        .env("HOOK_JSON", body.to_string())
+        .kill_on_drop(true)
+        .output();
+    let output = timeout(Duration::from_secs(secs), child)
+        .await
+        .map_err(|_| anyhow!("timeout"))??;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}, {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+// 本地中继不需要认证，只走最简单的smtp流程
+async fn send_mail(conf: &SmtpConf, subject: &str, text: &str) -> Result<()> {
+    let stream = TcpStream::connect(&conf.addr).await?;
+    let (rd, mut wr) = stream.into_split();
+    let mut rd = BufReader::new(rd);
+    smtp_reply(&mut rd, 220).await?;
+
+    let mut cmds = vec![
+        ("HELO forever".to_string(), 250),
+        (format!("MAIL FROM:<{}>", conf.from), 250),
+    ];
+    for to in &conf.to {
+        cmds.push((format!("RCPT TO:<{}>", to), 250));
+    }
+    cmds.push(("DATA".to_string(), 354));
+    for (cmd, code) in cmds {
+        wr.write_all(format!("{}\r\n", cmd).as_bytes()).await?;
+        smtp_reply(&mut rd, code).await?;
+    }
+
+    // 正文里单独一行的点要转义
+    let body: String = text
+        .lines()
+        .map(|l| {
+            if l.starts_with('.') {
+                format!(".{}\r\n", l)
+            } else {
+                format!("{}\r\n", l)
+            }
+        })
+        .collect();
+    let mail = format!(
+        "From: {}\r\nTo: {}\r\nSubject: [forever] {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}.\r\n",
+        conf.from,
+        conf.to.join(", "),
+        subject,
+        Local::now().to_rfc2822(),
+        body
+    );
+    wr.write_all(mail.as_bytes()).await?;
+    smtp_reply(&mut rd, 250).await?;
+    wr.write_all(b"QUIT\r\n").await?;
+    Ok(())
+}
+
+// 读一个应答，多行应答以 "250-" 开头，最后一行是 "250 "
+async fn smtp_reply<R: AsyncBufReadExt + Unpin>(rd: &mut R, code: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if rd.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("smtp connection closed"));
+        }
+        if line.get(3..4) == Some("-") {
+            continue;
+        }
+        if line.get(..3) != Some(code.to_string().as_str()) {
+            return Err(anyhow!("smtp want {}, got {}", code, line.trim_end()));
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HookEvent;
+    use crate::round::RoundEnd;
+    use crate::status::RoundStat;
+    use chrono::Local;
+
+    #[test]
+    fn test_round_event() {
+        let mut stat = RoundStat {
+            round: 3,
+            price: 1,
+            start: None,
+            end: Local::now(),
+            secs: 20,
+            result: RoundEnd::Failed,
+            sent: 4,
+            received: 4,
+            finished: 0,
+            failed: vec!["A1".into(), "A2".into(), "A3".into(), "A4".into()],
+        };
+        let ev = HookEvent::round(&stat);
+        assert_eq!(ev.name(), "round_failed");
+        assert!(ev.failed());
+        assert_eq!(
+            ev.message(),
+            "round 3 failed, finish=0/4, failed=4, use 20s"
+        );
+
+        stat.result = RoundEnd::Shutdown;
+        let ev = HookEvent::round(&stat);
+        assert_eq!(ev.name(), "round_stopped");
+        assert!(!ev.failed());
+
+        stat.result = RoundEnd::All;
+        assert!(!HookEvent::round(&stat).failed());
+    }
+}
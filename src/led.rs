@@ -0,0 +1,79 @@
+use crate::ew::{generate_random_string, EwConf};
+use anyhow_ext::{Context, Result};
+use log::{info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[derive(Serialize)]
+struct FlashLight {
+    colors: Vec<String>,
+    on_time: String,
+    led_rule: String, // 0是预置 1是接口
+    off_time: String,
+    flash_count: String,
+    sleep_time: String,
+    loop_count: String,
+    task_id: String,
+}
+
+#[derive(Serialize)]
+struct FlashControlData {
+    sid: String,
+    esl_id: String,
+    priority: u32,
+    back_url: String,
+    operation_type: String,
+    flash_light: FlashLight,
+}
+
+/// 价签闪灯，按200个一组下发
+pub async fn flash(conf: &EwConf, esl: &[String], colors: &[String], count: u32) -> Result<()> {
+    let client = Client::new();
+    let url = format!("http://{}/api3/{}/esls/control", conf.api, conf.uc);
+    let task_id = generate_random_string(12);
+    for esl_chunk in esl.chunks(200) {
+        let data: Vec<FlashControlData> = esl_chunk
+            .iter()
+            .map(|e| FlashControlData {
+                sid: generate_random_string(12),
+                esl_id: e.clone(),
+                priority: 10,
+                back_url: conf.back_url.clone(),
+                operation_type: "flash_light".to_string(),
+                flash_light: FlashLight {
+                    colors: colors.to_vec(),
+                    on_time: "100".to_string(),
+                    led_rule: "1".to_string(),
+                    off_time: "100".to_string(),
+                    flash_count: count.to_string(),
+                    sleep_time: "1000".to_string(),
+                    loop_count: "1".to_string(),
+                    task_id: task_id.clone(),
+                },
+            })
+            .collect();
+
+        let response = client
+            .put(&url)
+            .json(&json!({ "data": data }))
+            .send()
+            .await
+            .with_context(|| format!("发送 PUT 请求到 URL: {}", url))?;
+
+        if response.status().is_success() {
+            info!("flash request success for {} esl", esl_chunk.len());
+        } else {
+            let status = response.status();
+            let response_text = response.text().await.unwrap_or_default();
+            warn!(
+                "flash request failed, status: {}, {}",
+                status, response_text
+            );
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    Ok(())
+}
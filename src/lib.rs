@@ -0,0 +1,17 @@
+pub mod analyze;
+pub mod battery;
+pub mod ctrl;
+pub mod eslog;
+pub mod ew;
+pub mod history;
+pub mod hook;
+pub mod led;
+pub mod metrics;
+pub mod mock;
+pub mod pic;
+pub mod report;
+pub mod round;
+mod runloop;
+pub mod status;
+pub mod to18;
+pub mod tui;
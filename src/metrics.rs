@@ -0,0 +1,74 @@
+use prometheus::{
+    register_gauge, register_histogram, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, Gauge, Histogram, IntCounter, IntCounterVec, IntGauge,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+
+/// 下发给ew的价签更新数
+pub static UPDATES_SENT: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("forever_updates_sent_total", "esl updates sent to ew").unwrap()
+});
+
+/// ew接口错误，按状态码区分，连接失败为 connect
+pub static HTTP_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "forever_http_errors_total",
+        "ew api errors by status",
+        &["status"]
+    )
+    .unwrap()
+});
+
+/// 日志中下发价签的receive数
+pub static ESL_RECEIVE: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("forever_esl_receive_total", "esl receive events in ew log").unwrap()
+});
+
+/// 日志中下发价签的esl_update_finished数
+pub static ESL_FINISH: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("forever_esl_finish_total", "esl finished events in ew log").unwrap()
+});
+
+/// 每轮耗时
+pub static ROUND_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "forever_round_duration_seconds",
+        "duration of one update round",
+        vec![30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0, 7200.0]
+    )
+    .unwrap()
+});
+
+/// 按结束原因统计的轮次
+pub static ROUNDS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "forever_rounds_total",
+        "closed rounds by result",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// 上一轮完成比例
+pub static ROUND_COMPLETION: LazyLock<Gauge> = LazyLock::new(|| {
+    register_gauge!(
+        "forever_round_completion_ratio",
+        "finished / sent of the last round"
+    )
+    .unwrap()
+});
+
+/// 日志文件未读取的字节数
+pub static LOG_LAG: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("forever_log_tail_lag_bytes", "unread bytes of ew log").unwrap()
+});
+
+/// 输出 prometheus 文本格式
+pub fn gather() -> String {
+    let mut buf = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buf) {
+        log::warn!("encode metrics failed, {}", e);
+    }
+    String::from_utf8(buf).unwrap_or_default()
+}
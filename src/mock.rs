@@ -0,0 +1,185 @@
+use anyhow_ext::Result;
+use axum::extract::{Path, State};
+use axum::routing::{get, put};
+use axum::{Json, Router};
+use chrono::Local;
+use log::{debug, info, warn};
+use rand::{thread_rng, Rng};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+
+/// 模拟ew的配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MockConf {
+    pub addr: String,    // 监听地址
+    pub ewlog: String,   // 写入的假 eslworking.log
+    pub delay: [u64; 2], // 价签完成的延时范围 ms
+    pub failrate: f64,   // 失败比例 0-1，失败的价签写 status=failed
+    pub stuckrate: f64,  // 卡住比例 0-1，卡住的价签只有receive
+    pub callback: bool,  // 是否回调 back_url
+}
+
+impl Default for MockConf {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:19000".to_string(),
+            ewlog: "log/eslworking.log".to_string(),
+            delay: [500, 5000],
+            failrate: 0.0,
+            stuckrate: 0.0,
+            callback: true,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MockState {
+    conf: MockConf,
+    log: Arc<Mutex<File>>,
+    batches: Arc<Mutex<Vec<usize>>>,
+    client: Client,
+}
+
+/// 运行中的模拟ew
+pub struct MockEw {
+    pub addr: SocketAddr,
+    batches: Arc<Mutex<Vec<usize>>>,
+}
+
+impl MockEw {
+    /// 收到的每次下发的价签数
+    pub fn batches(&self) -> Vec<usize> {
+        self.batches.lock().unwrap().clone()
+    }
+}
+
+impl MockState {
+    // 按 eslworking.log 的格式写一行
+    fn write(&self, msg: &str) {
+        let mut f = self.log.lock().unwrap();
+        if let Err(e) = writeln!(
+            f,
+            "{} INFO {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            msg
+        ) {
+            warn!("write mock ew log failed, {}", e);
+        }
+    }
+}
+
+/// 启动模拟ew，监听成功后返回
+pub async fn start(conf: MockConf) -> Result<MockEw> {
+    if let Some(dir) = std::path::Path::new(&conf.ewlog).parent() {
+        create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&conf.ewlog)?;
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let listener = TcpListener::bind(&conf.addr).await?;
+    let addr = listener.local_addr()?;
+
+    let state = MockState {
+        conf,
+        log: Arc::new(Mutex::new(file)),
+        batches: batches.clone(),
+        client: Client::builder().timeout(Duration::from_secs(3)).build()?,
+    };
+    let app = Router::new()
+        .route("/api3/{uc}/esls", put(put_esls))
+        .route("/api3/esls/{id}", get(get_esl))
+        .with_state(state);
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("mock ew stopped, {}", e);
+        }
+    });
+    info!("mock ew listen on {}", addr);
+    Ok(MockEw { addr, batches })
+}
+
+/// 没有价签列表时生成一份假的
+pub fn fake_esl_list(fp: &str, count: u32) -> Result<()> {
+    if std::path::Path::new(fp).exists() {
+        return Ok(());
+    }
+    let mut file = File::create(fp)?;
+    for i in 1..=count {
+        let b = i.to_be_bytes();
+        writeln!(file, "{:02X}-{:02X}-{:02X}-{:02X}", b[0], b[1], b[2], b[3])?;
+    }
+    info!("write {} fake esl to {}", count, fp);
+    Ok(())
+}
+
+async fn put_esls(
+    State(st): State<MockState>,
+    Path(uc): Path<String>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    let items = body["data"].as_array().cloned().unwrap_or_default();
+    st.batches.lock().unwrap().push(items.len());
+    for item in items {
+        let esl = item["esl_id"].as_str().unwrap_or_default().to_string();
+        let sid = item["sid"].as_str().unwrap_or_default().to_string();
+        let back_url = item["back_url"].as_str().unwrap_or_default().to_string();
+        tokio::spawn(simulate(st.clone(), uc.clone(), esl, sid, back_url));
+    }
+    Json(json!({"error_code": 0, "error_msg": "success"}))
+}
+
+async fn get_esl(Path(id): Path<String>) -> Json<Value> {
+    Json(json!({
+        "error_code": 0,
+        "data": {
+            "esl_id": id,
+            "description": "mock",
+        }
+    }))
+}
+
+// 模拟一个价签的更新过程：receive -> esl_update_finished -> 回调
+async fn simulate(st: MockState, uc: String, esl: String, sid: String, back_url: String) {
+    let [min, max] = st.conf.delay;
+    let delay = thread_rng().gen_range(min..=max.max(min));
+    let stuck = thread_rng().gen::<f64>() < st.conf.stuckrate;
+    let ok = !stuck && thread_rng().gen::<f64>() >= st.conf.failrate;
+
+    sleep(Duration::from_millis(delay / 2)).await;
+    st.write(&format!(
+        "category=esl,action=receive,user_code={},eslid={},payload_type=UPDATE,payload_retry_time=0",
+        uc, esl
+    ));
+    sleep(Duration::from_millis(delay - delay / 2)).await;
+    let status = if ok { "success" } else { "failed" };
+    if !stuck {
+        st.write(&format!(
+            "category=esl,action=esl_update_finished,user_code={},eslid={},status={}",
+            uc, esl, status
+        ));
+    }
+
+    if st.conf.callback && !back_url.is_empty() {
+        let ret = st
+            .client
+            .post(&back_url)
+            .json(&json!({"sid": sid, "esl_id": esl, "status": status}))
+            .send()
+            .await;
+        if let Err(e) = ret {
+            debug!("mock callback {} failed, {}", back_url, e);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow_ext::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{GenericImageView, ImageOutputFormat, Rgba, RgbaImage};
+use log::info;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use rusttype::{point, Font, Scale};
+use std::fs;
+use std::io::{Cursor, Read};
+
+const TTF_DATA: &[u8] = include_bytes!("SourceCodePro-Black.ttf");
+
+const TEST_PNG: &[u8] = include_bytes!("test.png");
+
+// 读取png转为图片
+pub fn make_self_pic(fpdir: String) -> Result<String> {
+    // 打开文件
+    let mut file = fs::File::open(fpdir)?;
+    // 读取文件内容到缓冲区
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    // 将缓冲区编码为 Base64 字符串
+    let encoded = STANDARD.encode(&buffer);
+    // 打印或使用 encoded 字符串
+    println!("Base64 encoded PNG: {}", encoded);
+    Ok(encoded)
+}
+
+/// 生成的模版为15m 大模版
+pub fn make_auto_pic(random_number: i32) -> String {
+    // 读取 PNG 并打乱像素（保持不变）
+    // let img = image::open("src/test.png").expect("test.png need in you src path");
+    let img = image::load_from_memory(TEST_PNG).expect("load png file failed");
+    let (width, height) = img.dimensions();
+    let binding = img.to_rgba8();
+    let mut pixels: Vec<_> = binding.pixels().collect();
+    let mut rng = thread_rng();
+    pixels.shuffle(&mut rng);
+
+    // 新建输出图，先把打乱的像素铺上去
+    let mut output_image = RgbaImage::new(width, height);
+    for (i, pix) in pixels.into_iter().enumerate() {
+        let x = (i as u32) % width;
+        let y = (i as u32) / width;
+        output_image.put_pixel(x, y, *pix);
+    }
+
+    // 加载字体
+    // let font_data = include_bytes!("SourceCodePro-Black.ttf") as &[u8];
+    let font = Font::try_from_bytes(TTF_DATA).expect("Error loading font");
+
+    // 将数字拆成字符
+    let text = random_number.to_string();
+    let chars: Vec<char> = text.chars().collect();
+    let n = chars.len() as u32;
+
+    // 计算每个字符占据的“格子”宽度
+    let cell_w = width / n;
+
+    // 字体大小：让字高占图片高度的 80%
+    let scale_value = height as f32 * 0.8;
+    let scale = Scale {
+        x: scale_value,
+        y: scale_value,
+    };
+
+    // 垂直居中时的 baseline（v_metrics.ascent 是从 baseline 到字顶）
+    let v_metrics = font.v_metrics(scale);
+    let y_offset = ((height as f32 - (v_metrics.ascent - v_metrics.descent)) / 2.0) as u32;
+
+    // 对每个字符，单独 layout 并绘制
+    for (i, &c) in chars.iter().enumerate() {
+        // layout 出单个字符的 glyph
+        let glyphs: Vec<_> = font
+            .layout(&c.to_string(), scale, point(0.0, v_metrics.ascent))
+            .collect();
+        // 取第一个 glyph（对于单字符 layout 就是它）
+        if let Some(glyph) = glyphs.first() {
+            if let Some(bb) = glyph.pixel_bounding_box() {
+                // 计算此字符在整张图中的 x 起点：让它在第 i 格中水平居中
+                let glyph_w = bb.width() as u32;
+                let x0 = i as u32 * cell_w + (cell_w.saturating_sub(glyph_w)) / 2;
+                // 绘制：用纯白、全不透明
+                glyph.draw(|gx, gy, v| {
+                    if v > 0.5 {
+                        let px = x0 + gx;
+                        let py = y_offset + gy;
+                        if px < width && py < height {
+                            output_image.put_pixel(px, py, Rgba([255, 255, 255, 255]));
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // 导出 Base64
+    let mut buffer = Cursor::new(Vec::new());
+    output_image
+        .write_to(&mut buffer, ImageOutputFormat::Png)
+        .unwrap();
+    let image_data = buffer.into_inner();
+    info!("make pic finish");
+    output_image.save("output_image_with_number.png").unwrap();
+    STANDARD.encode(&image_data)
+}
+
+/// 生成价格图片并保存，render 子命令用
+pub fn render(price: i32, out: &str) -> Result<()> {
+    let data = STANDARD.decode(make_auto_pic(price))?;
+    fs::write(out, data)?;
+    info!("render price {} to {}", price, out);
+    Ok(())
+}
+
+/// 纯白图片，清屏用
+pub fn make_blank_pic(width: u32, height: u32) -> String {
+    let output_image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    let mut buffer = Cursor::new(Vec::new());
+    output_image
+        .write_to(&mut buffer, ImageOutputFormat::Png)
+        .unwrap();
+    STANDARD.encode(buffer.into_inner())
+}
@@ -0,0 +1,113 @@
+use crate::status::{EslStat, Status};
+use anyhow_ext::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::Path;
+
+/// 退出时写入的汇总报告
+pub const REPORT_FILE: &str = "log/report.txt";
+
+/// 单个价签的汇总
+#[derive(Debug, Clone, Serialize)]
+pub struct EslReport {
+    pub esl: String,
+    pub finished: u32,
+    pub failed: u32,
+    pub avg_ms: Option<u64>, // 平均耗时
+}
+
+/// 整个运行的汇总
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub rounds: u32,
+    pub updates: usize, // 下发价签数
+    pub finished: u32,
+    pub failed: u32,
+    pub total_ms: u64,
+    pub esls: Vec<EslReport>, // 失败多的在前
+}
+
+impl Report {
+    pub fn new(rounds: u32, updates: usize, esls: &BTreeMap<String, EslStat>) -> Self {
+        let mut report = Self {
+            rounds,
+            updates,
+            ..Default::default()
+        };
+        for (e, st) in esls {
+            report.finished += st.finished;
+            report.failed += st.failed;
+            report.total_ms += st.total_ms;
+            report.esls.push(EslReport {
+                esl: e.clone(),
+                finished: st.finished,
+                failed: st.failed,
+                avg_ms: (st.finished > 0).then(|| st.total_ms / st.finished as u64),
+            });
+        }
+        report.esls.sort_by_key(|e| std::cmp::Reverse(e.failed));
+        report
+    }
+
+    pub fn from_status(st: &Status) -> Self {
+        Self::new(st.round, st.updates, &st.esls)
+    }
+
+    /// 完成比例 %，没有结束的轮次时为0
+    pub fn success_rate(&self) -> f64 {
+        let total = self.finished + self.failed;
+        if total == 0 {
+            return 0.0;
+        }
+        self.finished as f64 * 100.0 / total as f64
+    }
+
+    pub fn avg_ms(&self) -> Option<u64> {
+        (self.finished > 0).then(|| self.total_ms / self.finished as u64)
+    }
+
+    /// 一行的汇总，打日志用
+    pub fn summary(&self) -> String {
+        format!(
+            "rounds={}; updates={}; finished={}; failed={}; success={:.2}%; avg={}ms",
+            self.rounds,
+            self.updates,
+            self.finished,
+            self.failed,
+            self.success_rate(),
+            self.avg_ms().map_or("-".to_string(), |v| v.to_string())
+        )
+    }
+
+    /// 汇总加每个价签的明细
+    pub fn render(&self) -> String {
+        let mut out = self.summary();
+        out.push('\n');
+        let _ = writeln!(
+            out,
+            "{:<14} {:>8} {:>6} {:>8}",
+            "esl", "finished", "failed", "avg_ms"
+        );
+        for e in &self.esls {
+            let avg = e.avg_ms.map_or("-".to_string(), |v| v.to_string());
+            let _ = writeln!(
+                out,
+                "{:<14} {:>8} {:>6} {:>8}",
+                e.esl, e.finished, e.failed, avg
+            );
+        }
+        out
+    }
+
+    pub fn write(&self, fp: &str) -> Result<()> {
+        if let Some(dir) = Path::new(fp).parent() {
+            create_dir_all(dir)?;
+        }
+        let mut file = File::create(fp)?;
+        file.write_all(self.render().as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::eslog::{EslAction, EslEvent};
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// 单轮默认超时时间 s
+pub const ROUND_TIMEOUT: u64 = 3600;
+
+/// 一轮更新结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RoundEnd {
+    All,      // 下发的价签全部完成
+    Rate,     // 达到完成比例
+    Failed,   // 价签都有结果，但有失败的
+    Timeout,  // 超时
+    Manual,   // 接口触发
+    Shutdown, // 退出时等待超时
+}
+
+/// 跟踪一轮下发的价签，从update下发开始计时
+#[derive(Debug, Clone)]
+pub struct Round {
+    pub sent: HashSet<String>,
+    pub received: HashSet<String>,
+    pub finished: HashMap<String, u64>, // 完成的价签及耗时 ms
+    pub failed: HashSet<String>,        // 更新结束但status失败的价签
+    pub start: Instant,
+    start_at: NaiveDateTime, // 和日志时间比较用
+}
+
+impl Round {
+    pub fn new(esl: &[String]) -> Self {
+        Self {
+            sent: esl.iter().cloned().collect(),
+            received: HashSet::new(),
+            finished: HashMap::new(),
+            failed: HashSet::new(),
+            start: Instant::now(),
+            start_at: Local::now().naive_local(),
+        }
+    }
+
+    /// 回放历史日志用，从日志里的时间开始计时，价签在收到事件时加入
+    pub fn at(start_at: NaiveDateTime) -> Self {
+        Self {
+            sent: HashSet::new(),
+            received: HashSet::new(),
+            finished: HashMap::new(),
+            failed: HashSet::new(),
+            start: Instant::now(),
+            start_at,
+        }
+    }
+
+    /// 记录一条日志事件，不是本轮下发的价签忽略
+    pub fn on_event(&mut self, ev: &EslEvent) {
+        if !self.sent.contains(&ev.eslid) {
+            return;
+        }
+        match ev.action {
+            EslAction::Receive => {
+                self.received.insert(ev.eslid.clone());
+            }
+            // 失败的不算完成，后面成功了再算
+            EslAction::Finish(_) if ev.action.failed() => {
+                if !self.finished.contains_key(&ev.eslid) {
+                    self.failed.insert(ev.eslid.clone());
+                }
+            }
+            EslAction::Finish(_) => {
+                self.failed.remove(&ev.eslid);
+                // 日志里有时间用日志时间，没有就用当前读到的时间
+                let ms = match ev.time {
+                    Some(t) => (t - self.start_at).num_milliseconds().max(0) as u64,
+                    None => self.start.elapsed().as_millis() as u64,
+                };
+                self.finished.entry(ev.eslid.clone()).or_insert(ms);
+            }
+        }
+    }
+
+    /// 判断本轮是否可以结束，rate 为成功完成的百分比
+    pub fn check(&self, rate: u32, timeout: Duration) -> Option<RoundEnd> {
+        let sent = self.sent.len();
+        let finished = self.finished.len();
+        if finished >= sent {
+            Some(RoundEnd::All)
+        } else if rate < 100 && finished * 100 >= sent * rate as usize {
+            Some(RoundEnd::Rate)
+        } else if finished + self.failed.len() >= sent {
+            Some(RoundEnd::Failed)
+        } else if self.start.elapsed() >= timeout {
+            Some(RoundEnd::Timeout)
+        } else {
+            None
+        }
+    }
+
+    /// 下发中途退出，只保留实际下发的价签
+    pub fn keep(&mut self, esl: &[String]) {
+        self.sent = esl.iter().cloned().collect();
+    }
+
+    /// 下发了但没有成功完成的价签，记为失败
+    pub fn stragglers(&self) -> Vec<String> {
+        let mut v: Vec<String> = self
+            .sent
+            .iter()
+            .filter(|e| !self.finished.contains_key(*e))
+            .cloned()
+            .collect();
+        v.sort();
+        v
+    }
+
+    /// 下发了但日志里没有receive的价签
+    pub fn unreceived(&self) -> Vec<String> {
+        let mut v: Vec<String> = self.sent.difference(&self.received).cloned().collect();
+        v.sort();
+        v
+    }
+
+    /// 完成价签的耗时，从慢到快
+    pub fn latency(&self) -> Vec<(String, u64)> {
+        let mut v: Vec<(String, u64)> = self
+            .finished
+            .iter()
+            .map(|(e, ms)| (e.clone(), *ms))
+            .collect();
+        v.sort_by_key(|e| std::cmp::Reverse(e.1));
+        v
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Round, RoundEnd};
+    use crate::eslog::EslLog;
+    use std::time::Duration;
+
+    fn finish(log: &EslLog, esl: &str, status: &str) -> crate::eslog::EslEvent {
+        let line = format!(
+            "2024-11-10 10:00:10.000 category=esl,action=esl_update_finished,user_code=god.1,eslid={},status={}",
+            esl, status
+        );
+        log.parse(&line).unwrap()
+    }
+
+    #[test]
+    fn test_failed_status() {
+        let log = EslLog::new();
+        let esl = ["A".to_string(), "B".to_string()];
+        let mut round = Round::new(&esl);
+        round.on_event(&finish(&log, "A", "failed"));
+        assert!(round.finished.is_empty());
+        assert_eq!(round.check(50, Duration::from_secs(60)), None);
+
+        round.on_event(&finish(&log, "B", "timeout"));
+        assert_eq!(
+            round.check(50, Duration::from_secs(60)),
+            Some(RoundEnd::Failed)
+        );
+        assert_eq!(round.stragglers(), esl.to_vec());
+
+        // 重试成功后算完成
+        round.on_event(&finish(&log, "A", "success"));
+        assert_eq!(
+            round.check(50, Duration::from_secs(60)),
+            Some(RoundEnd::Rate)
+        );
+        assert_eq!(round.stragglers(), vec!["B".to_string()]);
+    }
+}
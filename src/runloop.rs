@@ -0,0 +1,222 @@
+use crate::ctrl::SHUTDOWN_TIMEOUT;
+use crate::eslog::{EslAction, EslLog};
+use crate::ew::{need_sleep_time, EwConf, RunTime};
+use crate::history;
+use crate::hook::HookEvent;
+use crate::metrics;
+use crate::report::{Report, REPORT_FILE};
+use crate::round::{Round, RoundEnd, ROUND_TIMEOUT};
+use crate::status::RoundStat;
+use chrono::Local;
+use log::{info, warn};
+use std::mem;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+impl EwConf {
+    // 循环更新用
+    pub async fn singlerun(mut self) {
+        info!("start loop only update");
+        while !self.ctrl.is_shutdown() {
+            self.reload_esl();
+            let _ = self.update().await;
+            let interval = self.ctrl.interval_or(self.autotime.unwrap());
+            self.ctrl.wait_next(interval).await;
+        }
+        self.finish();
+    }
+
+    // 退出前写汇总报告
+    fn finish(&self) {
+        let report = Report::from_status(&self.status.lock().unwrap());
+        info!("run finish; {}", report.summary());
+        let report_fp = self.report.as_deref().unwrap_or(REPORT_FILE);
+        match report.write(report_fp) {
+            Ok(()) => println!("{}\nreport write to {}", report.summary(), report_fp),
+            Err(e) => warn!("write report failed, {}", e),
+        }
+        log::logger().flush();
+    }
+
+    // 接口要求时重新读取价签列表
+    fn reload_esl(&mut self) {
+        if !self.ctrl.take_reload() {
+            return;
+        }
+        match self.get_esl_id() {
+            Ok(v) => self.esl_id_list = v,
+            Err(e) => warn!("reload esl list failed, {}", e),
+        }
+    }
+
+    // 结束一轮，没完成的价签记为失败
+    fn close_round(&mut self, round: &Round, end: RoundEnd) {
+        let finishtime = Local::now();
+        let td = RunTime {
+            st: self.starttime.unwrap_or(finishtime),
+            et: finishtime,
+        }
+        .timediff();
+
+        let unrecv = round.unreceived();
+        if !unrecv.is_empty() {
+            info!("{:?} not in recv list, please check", unrecv);
+        }
+        let failed = round.stragglers();
+        let stat = self
+            .status
+            .lock()
+            .unwrap()
+            .close_round(round, &failed, end, td.as_secs());
+        let history_fp = self.history.as_deref().unwrap_or(history::HISTORY_FILE);
+        if let Err(e) = history::append(history_fp, &stat) {
+            warn!("write round history failed, {}", e);
+        }
+        metrics::ROUND_DURATION.observe(td.as_secs_f64());
+        let result = format!("{:?}", end);
+        metrics::ROUNDS.with_label_values(&[result.as_str()]).inc();
+        if !round.sent.is_empty() {
+            metrics::ROUND_COMPLETION.set(round.finished.len() as f64 / round.sent.len() as f64);
+        }
+
+        self.fire_hooks(&stat);
+
+        if matches!(
+            end,
+            RoundEnd::Timeout | RoundEnd::Shutdown | RoundEnd::Failed
+        ) {
+            warn!(
+                "loop update {:?}; use second={:?}; finish={}/{}; failed={:?}",
+                end,
+                td,
+                round.finished.len(),
+                round.sent.len(),
+                failed
+            );
+        } else {
+            info!(
+                "loop update finish({:?}); use second={:?}; finish={}/{}; failed={:?}",
+                end,
+                td,
+                round.finished.len(),
+                round.sent.len(),
+                failed
+            );
+        }
+    }
+
+    // 一轮结束的通知
+    fn fire_hooks(&self, stat: &RoundStat) {
+        self.hook.fire(HookEvent::round(stat));
+
+        let conf = &self.hook.conf;
+        if let Some(minrate) = conf.minrate {
+            let rate = if stat.sent == 0 {
+                100.0
+            } else {
+                stat.finished as f64 * 100.0 / stat.sent as f64
+            };
+            if rate < minrate as f64 {
+                self.hook.fire(HookEvent::LowRate {
+                    round: stat.round,
+                    rate,
+                    minrate,
+                });
+            }
+        }
+        // 刚好达到连续失败轮数时通知一次
+        if let Some(n) = conf.failstreak {
+            let st = self.status.lock().unwrap();
+            for e in &stat.failed {
+                if st.esls.get(e).is_some_and(|s| s.streak == n) {
+                    self.hook.fire(HookEvent::EslFailing {
+                        esl: e.clone(),
+                        rounds: n,
+                    });
+                }
+            }
+        }
+    }
+
+    pub async fn run(mut self) {
+        let esl_log = EslLog::new();
+        let timeout = Duration::from_secs(self.roundtimeout.unwrap_or(ROUND_TIMEOUT));
+        let rate = self.finishrate.unwrap_or(100);
+        let poll = Duration::from_millis(self.pollms.unwrap_or(5000));
+        let grace = Duration::from_secs(self.shutdowntimeout.unwrap_or(SHUTDOWN_TIMEOUT));
+        let mut deadline: Option<Instant> = None; // 收到退出信号后的等待截止时间
+        let mut round = self
+            .round
+            .take()
+            .unwrap_or_else(|| Round::new(&self.esl_id_list));
+
+        // 循环读取日志，每次记录file seek 位置
+        loop {
+            let file_len = esl_log
+                .tail(&self.ewlog, &mut self.fileseek, |ev| {
+                    if round.sent.contains(&ev.eslid) {
+                        match ev.action {
+                            EslAction::Receive => metrics::ESL_RECEIVE.inc(),
+                            EslAction::Finish(_) => metrics::ESL_FINISH.inc(),
+                        }
+                    }
+                    round.on_event(&ev);
+                })
+                .expect("Unable to read ew log");
+            metrics::LOG_LAG.set(file_len.saturating_sub(self.fileseek) as i64);
+            self.status.lock().unwrap().progress(&round);
+
+            if self.ctrl.is_shutdown() && deadline.is_none() {
+                info!("shutdown, wait in-flight esl at most {:?}", grace);
+                deadline = Some(Instant::now() + grace);
+            }
+            let end = if self.ctrl.take_trigger() {
+                Some(RoundEnd::Manual)
+            } else {
+                round.check(rate, timeout)
+            };
+            let end = match deadline {
+                Some(t) if end.is_none() && Instant::now() >= t => Some(RoundEnd::Shutdown),
+                _ => end,
+            };
+            if let Some(end) = end {
+                self.close_round(&round, end);
+                if self.ctrl.is_shutdown() {
+                    break;
+                }
+                let now = self.clock.unwrap_or_else(|| Local::now().time());
+                if self.need_pause(now) {
+                    let sleeptime = need_sleep_time(&self.limittime);
+                    info!("sleep {}s pause, waiting log change", sleeptime + 30);
+                    self.ctrl.resting.store(true, Ordering::Relaxed);
+                    self.ctrl.sleep(Duration::from_secs(sleeptime + 30)).await;
+                    self.ctrl.resting.store(false, Ordering::Relaxed);
+                    self.fileseek = 0; // waiting log change
+                } else {
+                    info!("file seek={}", self.fileseek);
+                }
+                self.ctrl.wait_next(self.ctrl.interval_or(0)).await;
+                if self.ctrl.is_shutdown() {
+                    break;
+                }
+                self.reload_esl();
+                let _ = self.update().await;
+                round = mem::take(&mut self.round).unwrap_or_else(|| Round::new(&self.esl_id_list));
+            } else if !round.finished.is_empty() {
+                info!(
+                    "recv:={}; finish={};",
+                    round.received.len(),
+                    round.finished.len()
+                );
+            }
+            // 退出等待期间不能被打断，否则会空转读日志
+            if deadline.is_some() {
+                sleep(poll).await;
+            } else {
+                self.ctrl.sleep(poll).await;
+            }
+        }
+        self.finish();
+    }
+}
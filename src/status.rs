@@ -0,0 +1,113 @@
+use crate::round::{Round, RoundEnd};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// 单个价签的累计统计
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct EslStat {
+    pub finished: u32,        // 完成次数
+    pub failed: u32,          // 失败次数
+    pub last_ms: Option<u64>, // 最近一次耗时
+    pub total_ms: u64,        // 累计耗时，算平均用
+    pub streak: u32,          // 连续失败轮数
+}
+
+/// 一轮的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundStat {
+    pub round: u32,
+    pub price: i32,
+    pub start: Option<DateTime<Local>>,
+    pub end: DateTime<Local>,
+    pub secs: u64,
+    pub result: RoundEnd,
+    pub sent: usize,
+    pub received: usize,
+    pub finished: usize,
+    pub failed: Vec<String>,
+}
+
+/// 运行状态，update循环写入，界面等读取
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Status {
+    pub round: u32,
+    pub price: i32,
+    pub round_start: Option<DateTime<Local>>,
+    pub sent: usize,
+    pub received: usize,
+    pub finished: usize,
+    pub failed: usize,               // 上一轮失败数
+    pub latency: Vec<(String, u64)>, // 本轮完成价签耗时 ms，从慢到快
+    pub esls: BTreeMap<String, EslStat>,
+    pub last: Option<RoundStat>, // 上一轮结果
+    pub updates: usize,          // 累计下发价签数
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+impl Status {
+    /// 新一轮下发
+    pub fn start_round(&mut self, price: i32, sent: usize) {
+        self.round += 1;
+        self.price = price;
+        self.round_start = Some(Local::now());
+        self.sent = sent;
+        self.updates += sent;
+        self.received = 0;
+        self.finished = 0;
+        self.latency.clear();
+    }
+
+    /// 下发中途退出，按实际下发数修正
+    pub fn keep(&mut self, sent: usize) {
+        self.updates -= self.sent.saturating_sub(sent);
+        self.sent = sent;
+    }
+
+    /// 同步本轮进度
+    pub fn progress(&mut self, round: &Round) {
+        self.received = round.received.len();
+        self.finished = round.finished.len();
+        self.latency = round.latency();
+    }
+
+    /// 本轮结束，累计每个价签的结果
+    pub fn close_round(
+        &mut self,
+        round: &Round,
+        failed: &[String],
+        end: RoundEnd,
+        secs: u64,
+    ) -> RoundStat {
+        self.progress(round);
+        let stat = RoundStat {
+            round: self.round,
+            price: self.price,
+            start: self.round_start,
+            end: Local::now(),
+            secs,
+            result: end,
+            sent: self.sent,
+            received: self.received,
+            finished: self.finished,
+            failed: failed.to_vec(),
+        };
+        self.last = Some(stat.clone());
+        self.failed = failed.len();
+        for (e, ms) in &self.latency {
+            let st = self.esls.entry(e.clone()).or_default();
+            st.finished += 1;
+            st.last_ms = Some(*ms);
+            st.total_ms += ms;
+            st.streak = 0;
+        }
+        for e in failed {
+            let st = self.esls.entry(e.clone()).or_default();
+            st.failed += 1;
+            st.streak += 1;
+        }
+        stat
+    }
+}
@@ -0,0 +1,129 @@
+use crate::ctrl::SharedControl;
+use crate::status::{SharedStatus, Status};
+use chrono::Local;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{BarChart, Block, Gauge, Paragraph, Row, Table};
+use ratatui::Frame;
+use std::io;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// 耗时分布的区间 s
+const BUCKETS: [(u64, &str); 6] = [
+    (10, "<10s"),
+    (30, "<30s"),
+    (60, "<1m"),
+    (120, "<2m"),
+    (300, "<5m"),
+    (u64::MAX, ">5m"),
+];
+
+/// 最慢价签显示个数
+const SLOWEST: usize = 10;
+
+/// 在终端显示运行状态，按q退出界面，更新循环不受影响；
+/// 界面下 Ctrl-C 不会产生信号，按退出信号处理
+pub fn spawn(status: SharedStatus, ctrl: SharedControl) -> JoinHandle<io::Result<()>> {
+    tokio::task::spawn_blocking(move || {
+        let mut terminal = ratatui::init();
+        let ret = loop {
+            let st = status.lock().unwrap().clone();
+            if let Err(e) = terminal.draw(|f| draw(f, &st)) {
+                break Err(e);
+            }
+            match event::poll(Duration::from_secs(1)) {
+                Ok(true) => match event::read() {
+                    Ok(Event::Key(k)) if k.code == KeyCode::Char('q') => break Ok(()),
+                    Ok(Event::Key(k))
+                        if k.code == KeyCode::Char('c')
+                            && k.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        ctrl.shutdown();
+                        break Ok(());
+                    }
+                    Ok(_) => {}
+                    Err(e) => break Err(e),
+                },
+                Ok(false) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        ratatui::restore();
+        ret
+    })
+}
+
+fn draw(f: &mut Frame, st: &Status) {
+    let [head, gauge, body] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(3),
+        Constraint::Min(8),
+    ])
+    .areas(f.area());
+    let [hist, slow] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+
+    // 轮次、价格、耗时、计数
+    let elapsed = st
+        .round_start
+        .map(|t| (Local::now() - t).num_seconds())
+        .unwrap_or(0);
+    let text = format!(
+        "round={}  price={}  elapsed={}s\nsent={}  recv={}  finish={}  last failed={}",
+        st.round, st.price, elapsed, st.sent, st.received, st.finished, st.failed
+    );
+    f.render_widget(
+        Paragraph::new(text).block(Block::bordered().title("forever (q to close)")),
+        head,
+    );
+
+    let ratio = if st.sent > 0 {
+        (st.finished as f64 / st.sent as f64).min(1.0)
+    } else {
+        0.0
+    };
+    f.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title("finished"))
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(ratio),
+        gauge,
+    );
+
+    // 本轮耗时分布
+    let mut counts = [0u64; BUCKETS.len()];
+    for (_, ms) in &st.latency {
+        let secs = ms / 1000;
+        if let Some(i) = BUCKETS.iter().position(|(max, _)| secs < *max) {
+            counts[i] += 1;
+        }
+    }
+    let data: Vec<(&str, u64)> = BUCKETS
+        .iter()
+        .zip(counts.iter())
+        .map(|((_, name), c)| (*name, *c))
+        .collect();
+    f.render_widget(
+        BarChart::default()
+            .block(Block::bordered().title("latency"))
+            .data(data.as_slice())
+            .bar_width(5),
+        hist,
+    );
+
+    // 最慢的价签
+    let rows: Vec<Row> = st
+        .latency
+        .iter()
+        .take(SLOWEST)
+        .map(|(e, ms)| Row::new(vec![e.clone(), format!("{:.1}s", *ms as f64 / 1000.0)]))
+        .collect();
+    f.render_widget(
+        Table::new(rows, [Constraint::Length(16), Constraint::Length(10)])
+            .header(Row::new(vec!["esl", "use"]))
+            .block(Block::bordered().title("slowest")),
+        slow,
+    );
+}
@@ -0,0 +1,328 @@
+//! 用本地模拟ew和合成日志驱动 update/run 循环
+use chrono::NaiveTime;
+use forever::ew::{need_sleep_time, EwConf, ROTATE_SIZE};
+use forever::history;
+use forever::mock::{self, MockConf, MockEw};
+use forever::round::RoundEnd;
+use forever::status::SharedStatus;
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+/// 循环卡住时的兜底超时，正常情况下由控制信号推进
+const STUCK: Duration = Duration::from_secs(30);
+
+fn tmp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("forever-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path(dir: &Path, name: &str) -> String {
+    dir.join(name).to_str().unwrap().to_string()
+}
+
+// 轮询直到条件满足
+async fn wait_for(f: impl Fn() -> bool) {
+    while !f() {
+        sleep(Duration::from_millis(20)).await;
+    }
+}
+
+// 已经结束的轮数
+fn closed(status: &SharedStatus) -> u32 {
+    status.lock().unwrap().last.as_ref().map_or(0, |r| r.round)
+}
+
+async fn start_mock(ewlog: String, failrate: f64, stuckrate: f64) -> MockEw {
+    mock::start(MockConf {
+        addr: "127.0.0.1:0".to_string(),
+        ewlog,
+        delay: [0, 20],
+        failrate,
+        stuckrate,
+        callback: false,
+    })
+    .await
+    .unwrap()
+}
+
+// 生成价签列表，extra 覆盖默认配置
+fn make_conf(dir: &Path, ew: &MockEw, esl_count: u32, extra: Value) -> EwConf {
+    let epd_wl = path(dir, "esl.txt");
+    mock::fake_esl_list(&epd_wl, esl_count).unwrap();
+    let ewlog = path(dir, "eslworking.log");
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&ewlog)
+        .unwrap();
+
+    let mut v = json!({
+        "api": ew.addr.to_string(),
+        "uc": "god.1",
+        "back_url": "",
+        "epd_wl": epd_wl,
+        "ewlog": ewlog,
+        "startprice": 1,
+        "limittime": ["23:58", "23:59"],
+        "template": "TPL",
+        "pollms": 100,
+        "history": path(dir, "history.jsonl"),
+        "report": path(dir, "report.txt"),
+    });
+    if let (Some(v), Some(extra)) = (v.as_object_mut(), extra.as_object()) {
+        v.extend(extra.clone());
+    }
+    EwConf::from_conf(serde_json::from_value(v).unwrap())
+}
+
+#[tokio::test]
+async fn test_update_batches() {
+    let dir = tmp_dir("batches");
+    let ew = start_mock(path(&dir, "eslworking.log"), 0.0, 0.0).await;
+    let mut conf = make_conf(&dir, &ew, 450, json!({}));
+
+    conf.update().await.unwrap();
+    assert_eq!(ew.batches(), vec![200, 200, 50]);
+    assert_eq!(conf.startprice, 2);
+    let st = conf.status.lock().unwrap();
+    assert_eq!(st.round, 1);
+    assert_eq!(st.sent, 450);
+}
+
+#[tokio::test]
+async fn test_run_rounds() {
+    let dir = tmp_dir("rounds");
+    let ew = start_mock(path(&dir, "eslworking.log"), 0.0, 0.0).await;
+    let mut conf = make_conf(&dir, &ew, 10, json!({}));
+    let (status, ctrl) = (conf.status.clone(), conf.ctrl.clone());
+
+    // 暂停后每轮结束都停住，由 trigger 推进
+    ctrl.pause();
+    conf.update().await.unwrap();
+    let drive = async {
+        for n in 1..=3 {
+            wait_for(|| closed(&status) == n).await;
+            if n < 3 {
+                ctrl.trigger_now();
+            }
+        }
+        ctrl.shutdown();
+    };
+    timeout(STUCK, async { tokio::join!(conf.run(), drive) })
+        .await
+        .unwrap();
+
+    assert_eq!(ew.batches(), vec![10, 10, 10]);
+    let st = status.lock().unwrap();
+    assert_eq!(st.round, 3);
+    let last = st.last.as_ref().unwrap();
+    assert_eq!(last.round, 3);
+    assert_eq!(last.result, RoundEnd::All);
+    assert_eq!(last.finished, 10);
+    assert!(st.esls.values().all(|e| e.finished == 3 && e.failed == 0));
+    drop(st);
+    assert_eq!(
+        history::load(&path(&dir, "history.jsonl")).unwrap().len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn test_stuck_round_timeout() {
+    let dir = tmp_dir("stuck");
+    // 全部卡住，日志里只有receive
+    let ew = start_mock(path(&dir, "eslworking.log"), 0.0, 1.0).await;
+    let mut conf = make_conf(&dir, &ew, 5, json!({"roundtimeout": 1}));
+    let (status, ctrl) = (conf.status.clone(), conf.ctrl.clone());
+
+    ctrl.pause();
+    conf.update().await.unwrap();
+    let drive = async {
+        wait_for(|| closed(&status) == 1).await;
+        ctrl.shutdown();
+    };
+    timeout(STUCK, async { tokio::join!(conf.run(), drive) })
+        .await
+        .unwrap();
+
+    let st = status.lock().unwrap();
+    assert_eq!(st.round, 1);
+    let last = st.last.as_ref().unwrap();
+    assert_eq!(last.result, RoundEnd::Timeout);
+    assert_eq!(last.received, 5);
+    assert_eq!(last.failed.len(), 5);
+    assert!(st.esls.values().all(|e| e.failed == 1));
+}
+
+#[tokio::test]
+async fn test_failed_status() {
+    let dir = tmp_dir("failed");
+    // 全部失败，日志里有 status=failed 的结束行
+    let ew = start_mock(path(&dir, "eslworking.log"), 1.0, 0.0).await;
+    let mut conf = make_conf(&dir, &ew, 5, json!({}));
+    let (status, ctrl) = (conf.status.clone(), conf.ctrl.clone());
+
+    ctrl.pause();
+    conf.update().await.unwrap();
+    let drive = async {
+        wait_for(|| closed(&status) == 1).await;
+        ctrl.shutdown();
+    };
+    timeout(STUCK, async { tokio::join!(conf.run(), drive) })
+        .await
+        .unwrap();
+
+    let st = status.lock().unwrap();
+    let last = st.last.as_ref().unwrap();
+    assert_eq!(last.result, RoundEnd::Failed);
+    assert_eq!(last.finished, 0);
+    assert_eq!(last.failed.len(), 5);
+    assert!(st.esls.values().all(|e| e.failed == 1));
+}
+
+#[tokio::test]
+async fn test_log_rotation() {
+    let dir = tmp_dir("rotate");
+    // 模拟ew写到别的文件，ew日志由测试自己写
+    let ew = start_mock(path(&dir, "mock.log"), 0.0, 0.0).await;
+    let ewlog = path(&dir, "eslworking.log");
+    fs::write(&ewlog, "x".repeat(4095) + "\n").unwrap();
+    let mut conf = make_conf(&dir, &ew, 3, json!({}));
+    assert_eq!(conf.fileseek, 4096);
+    let (status, ctrl) = (conf.status.clone(), conf.ctrl.clone());
+
+    ctrl.pause();
+    conf.update().await.unwrap();
+    // 切割日志，新文件比原来的读取位置小
+    let mut file = File::create(&ewlog).unwrap();
+    for e in &conf.esl_id_list {
+        writeln!(
+            file,
+            "category=esl,action=esl_update_finished,user_code=god.1,eslid={},status=success",
+            e
+        )
+        .unwrap();
+    }
+    drop(file);
+    let drive = async {
+        wait_for(|| closed(&status) == 1).await;
+        ctrl.shutdown();
+    };
+    timeout(STUCK, async { tokio::join!(conf.run(), drive) })
+        .await
+        .unwrap();
+
+    let st = status.lock().unwrap();
+    assert_eq!(st.round, 1);
+    let last = st.last.as_ref().unwrap();
+    assert_eq!(last.round, 1);
+    assert_eq!(last.result, RoundEnd::All);
+    assert_eq!(last.finished, 3);
+}
+
+#[tokio::test]
+async fn test_auto_mode() {
+    let dir = tmp_dir("auto");
+    let ew = start_mock(path(&dir, "eslworking.log"), 0.0, 0.0).await;
+    let conf = make_conf(&dir, &ew, 5, json!({"auto": true, "autotime": 3600}));
+    let (status, ctrl) = (conf.status.clone(), conf.ctrl.clone());
+
+    // 不看日志，间隔由 trigger 跳过
+    let drive = async {
+        for n in 1..=3 {
+            wait_for(|| ew.batches().len() == n).await;
+            if n < 3 {
+                ctrl.trigger_now();
+            }
+        }
+        ctrl.shutdown();
+    };
+    timeout(STUCK, async { tokio::join!(conf.singlerun(), drive) })
+        .await
+        .unwrap();
+
+    assert_eq!(ew.batches(), vec![5, 5, 5]);
+    let st = status.lock().unwrap();
+    assert_eq!(st.round, 3);
+    assert!(st.last.is_none());
+}
+
+#[tokio::test]
+async fn test_limittime_pause() {
+    let dir = tmp_dir("limittime");
+    let ewlog = path(&dir, "eslworking.log");
+    // 日志超过切割大小，用稀疏文件不占空间
+    File::create(&ewlog)
+        .unwrap()
+        .set_len(ROTATE_SIZE + 1)
+        .unwrap();
+    let ew = start_mock(ewlog.clone(), 0.0, 0.0).await;
+    let mut conf = make_conf(&dir, &ew, 2, json!({"limittime": ["23:55", "23:59"]}));
+    let during = NaiveTime::from_hms_opt(23, 56, 0).unwrap();
+    let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+
+    assert_eq!(need_sleep_time(&conf.limittime), 240);
+    assert!(conf.need_pause(during));
+    assert!(!conf.need_pause(noon));
+
+    conf.clock = Some(during);
+    let (status, ctrl) = (conf.status.clone(), conf.ctrl.clone());
+    ctrl.pause();
+    conf.update().await.unwrap();
+    let drive = async {
+        // 第一轮结束后停在休眠时间段，不开始下一轮
+        wait_for(|| ctrl.resting.load(Ordering::Relaxed)).await;
+        assert_eq!(closed(&status), 1);
+        assert_eq!(status.lock().unwrap().round, 1);
+        assert_eq!(ew.batches().len(), 1);
+        // 日志切割后继续，新日志从头读
+        File::create(&ewlog).unwrap();
+        ctrl.trigger_now();
+        wait_for(|| closed(&status) == 2).await;
+        ctrl.shutdown();
+    };
+    timeout(STUCK, async { tokio::join!(conf.run(), drive) })
+        .await
+        .unwrap();
+
+    assert!(!ctrl.resting.load(Ordering::Relaxed));
+    let st = status.lock().unwrap();
+    assert_eq!(st.round, 2);
+    let last = st.last.as_ref().unwrap();
+    assert_eq!(last.result, RoundEnd::All);
+    assert_eq!(last.finished, 2);
+}
+
+#[tokio::test]
+async fn test_shutdown_report() {
+    let dir = tmp_dir("shutdown");
+    let ew = start_mock(path(&dir, "eslworking.log"), 0.0, 1.0).await;
+    let report_fp = path(&dir, "report.txt");
+    let mut conf = make_conf(
+        &dir,
+        &ew,
+        4,
+        json!({"shutdowntimeout": 1, "report": report_fp}),
+    );
+    let status = conf.status.clone();
+
+    conf.update().await.unwrap();
+    conf.ctrl.shutdown();
+    // 等价签完成超时后自己退出
+    timeout(STUCK, conf.run()).await.unwrap();
+
+    let st = status.lock().unwrap();
+    let last = st.last.as_ref().unwrap();
+    assert_eq!(st.round, 1);
+    assert_eq!(last.result, RoundEnd::Shutdown);
+    assert_eq!(last.failed.len(), 4);
+    let report = fs::read_to_string(&report_fp).unwrap();
+    assert!(report.starts_with("rounds=1; updates=4; finished=0; failed=4;"));
+}